@@ -0,0 +1,170 @@
+//! Structural diffing of parse trees, so that an editor can lay out again
+//! only the parts of an expression which changed.
+
+use std::cmp::max;
+use std::slice;
+use super::nodes::*;
+
+/// The location of a node.  Each step gives the index of a child list
+/// within its parent (in the order of `ParseNode::children`) and the index
+/// of the node within that list.  The list of top-level nodes has index 0.
+pub type Path = Vec<(usize, usize)>;
+
+/// The paths of the outermost nodes which differ between `old` and `new`.
+/// A node whose children changed, but which is otherwise the same, is not
+/// reported itself; its changed descendants are.  Nodes appended to or
+/// removed from the end of a list are reported at their index.
+pub fn diff(old: &[ParseNode], new: &[ParseNode]) -> Vec<Path> {
+    let mut out = Vec::new();
+    diff_lists(old, new, 0, &mut Vec::new(), &mut out);
+    out
+}
+
+fn diff_lists(old: &[ParseNode], new: &[ParseNode], list: usize, path: &mut Path, out: &mut Vec<Path>) {
+    for idx in 0..max(old.len(), new.len()) {
+        path.push((list, idx));
+        match (old.get(idx), new.get(idx)) {
+            (Some(a), Some(b)) if a == b => {}
+            (Some(a), Some(b)) if a.shell() == b.shell() => {
+                for (list, (a, b)) in a.children().into_iter().zip(b.children()).enumerate() {
+                    diff_lists(a, b, list, path, out);
+                }
+            }
+            _ => out.push(path.clone()),
+        }
+        path.pop();
+    }
+}
+
+impl ParseNode {
+    /// The lists of nodes nested directly within this node.  Empty lists,
+    /// such as a missing superscript, are included, so the number of lists
+    /// only depends on the kind of node (and the shape of stacks and arrays).
+    pub fn children(&self) -> Vec<&[ParseNode]> {
+        match *self {
+            ParseNode::Symbol(_) |
+            ParseNode::Rule(_) |
+            ParseNode::Kerning(_) |
+            ParseNode::Style(_) |
+            ParseNode::Extend(_, _) |
+            ParseNode::FixedDelimiter(_) |
+            ParseNode::Middle(_) |
+            ParseNode::MathStrut |
+            ParseNode::ColorSwitch(_) => vec![],
+
+            ParseNode::Delimited(ref delim) => vec![&delim.inner[..]],
+            ParseNode::Radical(ref rad) => vec![&rad.inner[..]],
+            ParseNode::GenFraction(ref frac) => vec![&frac.numerator[..], &frac.denominator[..]],
+            ParseNode::Scripts(ref scripts) => vec![
+                scripts.base.as_ref().map_or(&[][..], |base| slice::from_ref(&**base)),
+                scripts.superscript.as_ref().map_or(&[][..], |sup| &sup[..]),
+                scripts.subscript.as_ref().map_or(&[][..], |sub| &sub[..]),
+            ],
+            ParseNode::Accent(ref acc) => vec![&acc.nucleus[..]],
+            ParseNode::AtomChange(ref ac) => vec![&ac.inner[..]],
+            ParseNode::Color(ref clr) => vec![&clr.inner[..]],
+            ParseNode::Group(ref inner) => vec![&inner[..]],
+            ParseNode::Stack(ref stack) => stack.lines.iter().map(|line| &line[..]).collect(),
+            ParseNode::Array(ref array) => array.rows.iter()
+                .flat_map(|row| row.iter().map(|cell| &cell[..]))
+                .collect(),
+            ParseNode::Scale(ref sc) => vec![&sc.inner[..]],
+            ParseNode::VCenter(ref inner) |
+            ParseNode::Not(ref inner) |
+            ParseNode::Overline(ref inner) |
+            ParseNode::Underline(ref inner) |
+            ParseNode::PoorMansBold(ref inner) |
+            ParseNode::Transform(_, ref inner) => vec![&inner[..]],
+            ParseNode::Extensible(ref ext) => vec![&ext.over[..], &ext.under[..]],
+            ParseNode::RaiseBox(ref rb) => vec![&rb.inner[..]],
+            ParseNode::Lap(ref lap) => vec![&lap.inner[..]],
+            ParseNode::OverUnder(ref ou) => vec![&ou.base[..], &ou.over[..], &ou.under[..]],
+        }
+    }
+
+    /// A copy of this node with every child list emptied, so that two nodes
+    /// compare equal exactly when they differ at most in their children.
+    fn shell(&self) -> ParseNode {
+        let mut shell = self.clone();
+        match shell {
+            ParseNode::Delimited(ref mut delim) => delim.inner.clear(),
+            ParseNode::Radical(ref mut rad) => rad.inner.clear(),
+            ParseNode::GenFraction(ref mut frac) => {
+                frac.numerator.clear();
+                frac.denominator.clear();
+            }
+            ParseNode::Scripts(ref mut scripts) => {
+                if let Some(ref mut base) = scripts.base {
+                    **base = ParseNode::Group(Vec::new());
+                }
+                if let Some(ref mut sup) = scripts.superscript {
+                    sup.clear();
+                }
+                if let Some(ref mut sub) = scripts.subscript {
+                    sub.clear();
+                }
+            }
+            ParseNode::Accent(ref mut acc) => acc.nucleus.clear(),
+            ParseNode::AtomChange(ref mut ac) => ac.inner.clear(),
+            ParseNode::Color(ref mut clr) => clr.inner.clear(),
+            ParseNode::Group(ref mut inner) => inner.clear(),
+            ParseNode::Stack(ref mut stack) => stack.lines.iter_mut().for_each(Vec::clear),
+            ParseNode::Array(ref mut array) => array.rows.iter_mut()
+                .flat_map(|row| row.iter_mut())
+                .for_each(Vec::clear),
+            ParseNode::Scale(ref mut sc) => sc.inner.clear(),
+            ParseNode::VCenter(ref mut inner) |
+            ParseNode::Not(ref mut inner) |
+            ParseNode::Overline(ref mut inner) |
+            ParseNode::Underline(ref mut inner) |
+            ParseNode::PoorMansBold(ref mut inner) |
+            ParseNode::Transform(_, ref mut inner) => inner.clear(),
+            ParseNode::Extensible(ref mut ext) => {
+                ext.over.clear();
+                ext.under.clear();
+            }
+            ParseNode::RaiseBox(ref mut rb) => rb.inner.clear(),
+            ParseNode::Lap(ref mut lap) => lap.inner.clear(),
+            ParseNode::OverUnder(ref mut ou) => {
+                ou.base.clear();
+                ou.over.clear();
+                ou.under.clear();
+            }
+            _ => (),
+        }
+        shell
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dimensions::Unit;
+    use crate::testing::*;
+
+    fn frac(numerator: ParseNode, denominator: ParseNode) -> ParseNode {
+        ParseNode::GenFraction(GenFraction {
+            numerator: vec![numerator],
+            denominator: vec![denominator],
+            bar_thickness: BarThickness::Default,
+            left_delimiter: None,
+            right_delimiter: None,
+            style: MathStyle::NoChange,
+            bar_padding: Unit::Em(0.0),
+        })
+    }
+
+    #[test]
+    fn unchanged_siblings() {
+        let old = vec![ord('a'), frac(ord('x'), ord('y')), ord('b')];
+        assert!(diff(&old, &old).is_empty());
+
+        // Only the denominator changed, and `c` was appended.
+        let new = vec![ord('a'), frac(ord('x'), ord('z')), ord('b'), ord('c')];
+        assert_eq!(diff(&old, &new), vec![vec![(0, 1), (1, 0)], vec![(0, 3)]]);
+
+        // A node of another kind is reported as a whole.
+        let replaced = vec![ord('a'), ParseNode::Radical(Radical { inner: vec![ord('x')] }), ord('b')];
+        assert_eq!(diff(&old, &replaced), vec![vec![(0, 1)]]);
+    }
+}
@@ -0,0 +1,274 @@
+//! Write parse trees back out as TeX.  The output is not an exact inverse of
+//! the parser: it aims to be stable and readable, so that it can be used in
+//! error messages and to compare parse trees.
+
+use std::fmt::{self, Display, Write};
+use unicode_math::SYMBOLS;
+use crate::font::AtomType;
+use crate::layout::Style;
+use super::color::RGBA;
+use super::nodes::*;
+use super::symbols::Symbol;
+
+impl ParseNode {
+    /// An approximate TeX source for this node.
+    pub fn to_tex(&self) -> String {
+        self.to_string()
+    }
+}
+
+/// Write a list of nodes, without enclosing braces.
+fn write_nodes(f: &mut fmt::Formatter, nodes: &[ParseNode]) -> fmt::Result {
+    for node in nodes {
+        write!(f, "{}", node)?;
+    }
+    Ok(())
+}
+
+struct Braced<'a>(&'a [ParseNode]);
+impl<'a> Display for Braced<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_char('{')?;
+        write_nodes(f, self.0)?;
+        f.write_char('}')
+    }
+}
+
+impl Display for ParseNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseNode::Symbol(sym) => write!(f, "{}", sym),
+            ParseNode::Delimited(ref delim) => {
+                write!(f, "\\left{}", delim.left)?;
+                write_nodes(f, &delim.inner)?;
+                write!(f, "\\right{}", delim.right)
+            }
+            ParseNode::Radical(ref rad) => write!(f, "\\sqrt{}", Braced(&rad.inner)),
+            ParseNode::GenFraction(ref frac) => frac.fmt(f),
+            ParseNode::Scripts(ref scripts) => {
+                match scripts.base {
+                    Some(ref base) => match **base {
+                        ParseNode::Symbol(_) | ParseNode::Group(_) => write!(f, "{}", base)?,
+                        _ => write!(f, "{{{}}}", base)?,
+                    },
+                    None => f.write_str("{}")?,
+                }
+                if let Some(ref sup) = scripts.superscript {
+                    write!(f, "^{}", Braced(sup))?;
+                }
+                if let Some(ref sub) = scripts.subscript {
+                    write!(f, "_{}", Braced(sub))?;
+                }
+                Ok(())
+            }
+            ParseNode::Rule(rule) => write!(f, "\\rule{{{}}}{{{}}}", rule.width, rule.height),
+            ParseNode::Kerning(kern) => write!(f, "\\kern{{{}}}", kern),
+            ParseNode::Accent(ref acc) => write!(f, "{}{}", acc.symbol, Braced(&acc.nucleus)),
+            ParseNode::Style(style) => f.write_str(match style {
+                Style::Display | Style::DisplayCramped => "\\displaystyle ",
+                Style::Text | Style::TextCramped => "\\textstyle ",
+                Style::Script | Style::ScriptCramped => "\\scriptstyle ",
+                Style::ScriptScript | Style::ScriptScriptCramped => "\\scriptscriptstyle ",
+            }),
+            ParseNode::AtomChange(ref ac) => {
+                write!(f, "{}{}", atom_command(ac.at), Braced(&ac.inner))
+            }
+            ParseNode::Color(ref clr) => write!(f, "\\textcolor{{{}}}{}", clr.color, Braced(&clr.inner)),
+            ParseNode::ColorSwitch(color) => write!(f, "\\color{{{}}}", color),
+            ParseNode::Not(ref inner) => {
+                f.write_str("\\not")?;
+                match inner.len() {
+                    1 => write!(f, "{}", inner[0]),
+                    _ => write!(f, "{}", Braced(inner)),
+                }
+            }
+            ParseNode::Group(ref inner) => write!(f, "{}", Braced(inner)),
+            ParseNode::Stack(ref stack) => {
+                f.write_str("\\substack{")?;
+                for (i, line) in stack.lines.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str("\\\\")?;
+                    }
+                    write_nodes(f, line)?;
+                }
+                f.write_char('}')
+            }
+            ParseNode::Extend(codepoint, height) => {
+                write!(f, "\\vextend{{{}}}{{{}}}", Symbol { codepoint, atom_type: AtomType::Ordinal }, height)
+            }
+            ParseNode::Array(ref array) => write!(f, "{}", array),
+            ParseNode::Scale(ref sc) => write!(f, "\\scalebox{{{}}}{}", sc.factor, Braced(&sc.inner)),
+            ParseNode::VCenter(ref inner) => write!(f, "\\vcenter{}", Braced(inner)),
+            ParseNode::Transform(t, ref inner) => write!(
+                f, "\\transform{{{} {} {} {} {} {}}}{}",
+                t.xx, t.xy, t.yx, t.yy, t.tx, t.ty, Braced(inner)
+            ),
+            ParseNode::PoorMansBold(ref inner) => write!(f, "\\pmb{}", Braced(inner)),
+            ParseNode::Overline(ref inner) => write!(f, "\\overline{}", Braced(inner)),
+            ParseNode::Underline(ref inner) => write!(f, "\\underline{}", Braced(inner)),
+            ParseNode::Lap(ref lap) => {
+                let command = match lap.side {
+                    LapSide::Left => "\\llap",
+                    LapSide::Right => "\\rlap",
+                    LapSide::Center => "\\clap",
+                };
+                write!(f, "{}{}", command, Braced(&lap.inner))
+            }
+            ParseNode::Extensible(ref ext) => {
+                write!(f, "\\xextensible{}{}{}", ext.symbol, Braced(&ext.under), Braced(&ext.over))
+            }
+            ParseNode::RaiseBox(ref rb) => write!(f, "\\raisebox{{{}}}{}", rb.amount, Braced(&rb.inner)),
+            ParseNode::OverUnder(ref ou) => {
+                if !ou.under.is_empty() {
+                    write!(f, "\\underset{}{{", Braced(&ou.under))?;
+                }
+                if !ou.over.is_empty() {
+                    write!(f, "\\overset{}{{", Braced(&ou.over))?;
+                }
+                write_nodes(f, &ou.base)?;
+                if !ou.over.is_empty() {
+                    f.write_char('}')?;
+                }
+                if !ou.under.is_empty() {
+                    f.write_char('}')?;
+                }
+                Ok(())
+            }
+            ParseNode::MathStrut => f.write_str("\\mathstrut "),
+            ParseNode::Middle(sym) => write!(f, "\\middle{}", sym),
+            ParseNode::FixedDelimiter(fd) => {
+                let size = match fd.size {
+                    0 => "",
+                    1 => "big",
+                    2 => "Big",
+                    3 => "bigg",
+                    _ => "Bigg",
+                };
+                let side = match fd.symbol.atom_type {
+                    AtomType::Open => "l",
+                    AtomType::Close => "r",
+                    AtomType::Relation => "m",
+                    _ => "",
+                };
+                if !size.is_empty() {
+                    write!(f, "\\{}{}", size, side)?;
+                }
+                write!(f, "{}", fd.symbol)
+            }
+        }
+    }
+}
+
+impl Display for GenFraction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let plain = self.bar_thickness == BarThickness::Default
+            && self.left_delimiter.is_none()
+            && self.right_delimiter.is_none()
+            && self.style == MathStyle::NoChange;
+        if plain {
+            return write!(f, "\\frac{}{}", Braced(&self.numerator), Braced(&self.denominator));
+        }
+
+        f.write_str("\\genfrac")?;
+        for delim in &[self.left_delimiter, self.right_delimiter] {
+            match *delim {
+                Some(sym) => write!(f, "{{{}}}", sym)?,
+                None => f.write_str("{}")?,
+            }
+        }
+        match self.bar_thickness {
+            BarThickness::Default => f.write_str("{}")?,
+            BarThickness::None => f.write_str("{0pt}")?,
+            BarThickness::Unit(unit) => write!(f, "{{{}}}", unit)?,
+        }
+        f.write_str(match self.style {
+            MathStyle::Display => "{0}",
+            MathStyle::Text => "{1}",
+            MathStyle::NoChange => "{}",
+        })?;
+        write!(f, "{}{}", Braced(&self.numerator), Braced(&self.denominator))
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.codepoint {
+            '{' | '}' | '#' | '$' | '%' | '&' | '_' => write!(f, "\\{}", self.codepoint),
+            c if c.is_ascii() => f.write_char(c),
+            c => match SYMBOLS.iter().find(|sym| sym.codepoint == c) {
+                // The space keeps a following letter from extending the name.
+                Some(sym) => write!(f, "\\{} ", sym.name),
+                None => f.write_char(c),
+            }
+        }
+    }
+}
+
+impl Display for RGBA {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let RGBA(r, g, b, a) = *self;
+        if a == 0xff {
+            write!(f, "#{:02x}{:02x}{:02x}", r, g, b)
+        } else {
+            write!(f, "#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+        }
+    }
+}
+
+fn atom_command(at: AtomType) -> &'static str {
+    match at {
+        AtomType::Binary => "\\mathbin",
+        AtomType::Relation => "\\mathrel",
+        AtomType::Open => "\\mathopen",
+        AtomType::Close => "\\mathclose",
+        AtomType::Punctuation => "\\mathpunct",
+        AtomType::Operator(_) => "\\mathop",
+        AtomType::Inner => "\\mathinner",
+        _ => "\\mathord",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dimensions::Unit;
+    use crate::testing::*;
+
+    #[test]
+    fn representative_trees() {
+        let frac = GenFraction {
+            numerator: vec![ord('a')],
+            denominator: vec![ord('b')],
+            bar_thickness: BarThickness::Default,
+            left_delimiter: None,
+            right_delimiter: None,
+            style: MathStyle::NoChange,
+            bar_padding: Unit::Em(0.0),
+        };
+        assert_eq!(ParseNode::GenFraction(frac.clone()).to_tex(), "\\frac{a}{b}");
+        let binom = GenFraction {
+            bar_thickness: BarThickness::None,
+            left_delimiter: Some(Symbol { codepoint: '(', atom_type: AtomType::Open }),
+            right_delimiter: Some(Symbol { codepoint: ')', atom_type: AtomType::Close }),
+            ..frac
+        };
+        assert_eq!(ParseNode::GenFraction(binom).to_tex(), "\\genfrac{(}{)}{0pt}{}{a}{b}");
+
+        let scripts = ParseNode::Scripts(Scripts {
+            base: Some(Box::new(named("sum"))),
+            superscript: Some(vec![ord('n')]),
+            subscript: Some(vec![ord('i'), sym('=', AtomType::Relation), ord('0')]),
+        });
+        assert_eq!(scripts.to_tex(), "\\sum ^{n}_{i=0}");
+
+        let delimited = ParseNode::Delimited(Delimited {
+            left: Symbol { codepoint: '{', atom_type: AtomType::Open },
+            right: Symbol { codepoint: '.', atom_type: AtomType::Close },
+            inner: vec![ParseNode::Radical(Radical { inner: vec![ord('x')] })],
+        });
+        assert_eq!(delimited.to_tex(), "\\left\\{\\sqrt{x}\\right.");
+
+        let color = ParseNode::Color(Color { color: RGBA(0xff, 0, 0, 0xff), inner: vec![ord('x')] });
+        assert_eq!(format!("{}", color), "\\textcolor{#ff0000}{x}");
+    }
+}
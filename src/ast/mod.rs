@@ -4,6 +4,8 @@ pub mod engine;
 pub mod nodes;
 pub mod color;
 pub mod symbols;
+mod display;
+pub mod diff;
 
 pub use self::engine::*;
 pub use self::nodes::ParseNode;
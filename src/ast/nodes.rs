@@ -1,15 +1,32 @@
-use crate::dimensions::{Unit};
+use crate::dimensions::{AffineTransform, Unit, f64_bits, hash_f64};
 use crate::layout::Style;
 use crate::error::{ParseResult, ParseError};
 use super::color::RGBA;
-use crate::environments::Array;
+use crate::environments::{Array, ArrayColumnAlign};
 use crate::font::{AtomType};
 use super::symbols::Symbol;
+use std::hash::{Hash, Hasher};
+use std::mem;
 
 // TODO: It might be worth letting the `Group` variant
 // to have an atomtype associated with it.  By default,
 // it will be a `Ordinal`.
-#[derive(Debug, PartialEq, Clone)]
+/// `ParseNode` implements `Hash` and `Eq`, so a parsed expression may be
+/// used directly as a cache key for its layout.  Since a `Layout` depends on
+/// the `LayoutSettings` as well, keep one cache per font and font size:
+///
+/// ```ignore
+/// let mut cache: HashMap<Vec<ParseNode>, Layout> = HashMap::new();
+/// let layout = match cache.get(&nodes) {
+///     Some(layout) => layout.clone(),
+///     None => {
+///         let layout = engine::layout(&nodes, settings)?;
+///         cache.insert(nodes.clone(), layout.clone());
+///         layout
+///     }
+/// };
+/// ```
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub enum ParseNode {
     Symbol(Symbol),
     Delimited(Delimited),
@@ -26,54 +43,140 @@ pub enum ParseNode {
     Stack(Stack),
     Extend(char, Unit),
     Array(Array),
+    Scale(SizeChange),
+    VCenter(Vec<ParseNode>),
+    Extensible(Extensible),
+    RaiseBox(RaiseBox),
+    FixedDelimiter(FixedDelimiter),
+    OverUnder(OverUnder),
+    Middle(Symbol),
+    MathStrut,
+    /// A color change which lasts until the end of the enclosing group,
+    /// as `\color{red}` without braces.
+    ColorSwitch(RGBA),
+    /// A relation struck through by a slash, as `\not=`.
+    Not(Vec<ParseNode>),
+    Overline(Vec<ParseNode>),
+    Underline(Vec<ParseNode>),
+    Lap(Lap),
+    /// Content overprinted with small offsets, to fake a bold weight, as
+    /// `\pmb`.
+    PoorMansBold(Vec<ParseNode>),
+    /// Content drawn through an affine transform, such as a slant.  The
+    /// transform is given in pixels, with the origin on the baseline at the
+    /// start of the content.
+    Transform(AffineTransform, Vec<ParseNode>),
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Stack {
     pub atom_type: AtomType,
     pub lines: Vec<Vec<ParseNode>>,
+    /// Additional vertical space after each line.  `None` keeps the default gap.
+    pub line_gaps: Vec<Option<Unit>>,
+    /// The horizontal alignment of the lines.  Default: centered.
+    pub alignment: ArrayColumnAlign,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub struct Delimited {
     pub left: Symbol,
     pub right: Symbol,
     pub inner: Vec<ParseNode>,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub struct Scripts {
     pub base: Option<Box<ParseNode>>,
     pub superscript: Option<Vec<ParseNode>>,
     pub subscript: Option<Vec<ParseNode>>,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct AtomChange {
     pub at: AtomType,
     pub inner: Vec<ParseNode>,
+    /// For an operator with limits, whether they are only set above and
+    /// below in display style, as for `\lim`.  An explicit `\limits` clears
+    /// this, so that the limits are used in every style.
+    pub display_limits: bool,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+/// A relation, such as an arrow or `=`, stretched to fit the labels
+/// above and below it, as in `\xrightarrow` or `\xlongequal`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Extensible {
+    pub symbol: Symbol,
+    pub over: Vec<ParseNode>,
+    pub under: Vec<ParseNode>,
+}
+
+/// Shift `inner` up by `amount`, or down if it is negative.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RaiseBox {
+    pub amount: Unit,
+    pub inner: Vec<ParseNode>,
+}
+
+/// Content of zero width, as `\rlap`, which overlaps its neighbours on the
+/// given side: `Right` for `\rlap`, `Left` for `\llap` and `Center` for
+/// `\clap`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Lap {
+    pub side: LapSide,
+    pub inner: Vec<ParseNode>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum LapSide {
+    Left,
+    Right,
+    Center,
+}
+
+/// A delimiter of fixed size, independent of the surrounding content, as
+/// produced by `\big`, `\Big`, `\bigg` and `\Bigg` (sizes 1 to 4).  The
+/// size selects the variant of the delimiter in the MATH table, where 0 is
+/// the glyph itself.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FixedDelimiter {
+    pub symbol: Symbol,
+    pub size: u8,
+}
+
+/// Content set above and below a base, as in `\overset` and `\underset`.
+/// The result takes the atom type of the base, so that `\overset{!}{=}` is
+/// still spaced as a relation, unless `atom_type` overrides it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OverUnder {
+    pub base: Vec<ParseNode>,
+    pub over: Vec<ParseNode>,
+    pub under: Vec<ParseNode>,
+    pub atom_type: Option<AtomType>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Accent {
     pub symbol: Symbol,
     pub nucleus: Vec<ParseNode>,
 }
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub struct Rule {
     pub width: Unit,
     pub height: Unit,
     //pub depth:  Unit,
+    /// The color of the rule.  `None` uses the surrounding color.
+    pub color: Option<RGBA>,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub struct Radical {
     pub inner: Vec<ParseNode>,
     // pub superscript: Vec<ParseNode>,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub struct GenFraction {
     pub numerator: Vec<ParseNode>,
     pub denominator: Vec<ParseNode>,
@@ -81,28 +184,77 @@ pub struct GenFraction {
     pub left_delimiter: Option<Symbol>,
     pub right_delimiter: Option<Symbol>,
     pub style: MathStyle,
+    /// How far the bar extends beyond the wider of the numerator and the
+    /// denominator, on each side.  Zero gives the usual TeX fraction.
+    pub bar_padding: Unit,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Color {
     pub color: RGBA,
     pub inner: Vec<ParseNode>,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+/// Scale the font size of `inner` by `factor`, independent of the math style.
+#[derive(Debug, Clone)]
+pub struct SizeChange {
+    pub factor: f64,
+    pub inner: Vec<ParseNode>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum BarThickness {
     Default,
     None,
     Unit(Unit),
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum MathStyle {
     Display,
     Text,
     NoChange,
 }
 
+impl Hash for Stack {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(&self.atom_type).hash(state);
+        self.lines.hash(state);
+        self.line_gaps.hash(state);
+        self.alignment.hash(state);
+    }
+}
+
+impl Hash for AtomChange {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(&self.at).hash(state);
+        self.inner.hash(state);
+        self.display_limits.hash(state);
+    }
+}
+
+impl Hash for OverUnder {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.base.hash(state);
+        self.over.hash(state);
+        self.under.hash(state);
+        self.atom_type.as_ref().map(mem::discriminant).hash(state);
+    }
+}
+
+impl PartialEq for SizeChange {
+    fn eq(&self, other: &SizeChange) -> bool {
+        f64_bits(self.factor) == f64_bits(other.factor) && self.inner == other.inner
+    }
+}
+impl Eq for SizeChange {}
+impl Hash for SizeChange {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_f64(self.factor, state);
+        self.inner.hash(state);
+    }
+}
+
 impl ParseNode {
     pub fn expect_left(self) -> ParseResult<'static, Symbol> {
         if let ParseNode::Symbol(sym) = self {
@@ -130,6 +282,18 @@ impl ParseNode {
         }
     }
 
+    /// An operator named by upright text, as `\lim`.  With `limits`, its
+    /// scripts are set above and below it in display style.
+    pub fn text_operator(name: &str, limits: bool) -> ParseNode {
+        ParseNode::AtomChange(AtomChange {
+            at: AtomType::Operator(limits),
+            inner: name.chars()
+                .map(|codepoint| ParseNode::Symbol(Symbol { codepoint, atom_type: AtomType::Alpha }))
+                .collect(),
+            display_limits: limits,
+        })
+    }
+
     pub fn set_atom_type(&mut self, at: AtomType) {
         match *self {
             ParseNode::Symbol(ref mut sym) => sym.atom_type = at,
@@ -138,7 +302,10 @@ impl ParseNode {
                     b.set_atom_type(at);
                 }
             }
-            ParseNode::AtomChange(ref mut node) => node.at = at,
+            ParseNode::AtomChange(ref mut node) => {
+                node.at = at;
+                node.display_limits = false;
+            }
             ParseNode::Stack(Stack { ref mut atom_type, .. }) => *atom_type = at,
             _ => (),
         }
@@ -152,6 +319,7 @@ impl ParseNode {
             ParseNode::Accent(ref acc) => is_symbol(&acc.nucleus),
             ParseNode::AtomChange(ref ac) => is_symbol(&ac.inner),
             ParseNode::Color(ref clr) => is_symbol(&clr.inner),
+            ParseNode::Scale(ref sc) => is_symbol(&sc.inner),
             _ => None,
         }
     }
@@ -181,6 +349,26 @@ impl ParseNode {
 
             ParseNode::Extend(_,_)   => AtomType::Inner,
             ParseNode::Array(_)      => AtomType::Inner,
+            ParseNode::VCenter(_)    => AtomType::Alpha,
+            ParseNode::Extensible(_) => AtomType::Relation,
+            ParseNode::RaiseBox(_)   => AtomType::Alpha,
+            ParseNode::FixedDelimiter(ref fd) => fd.symbol.atom_type,
+            ParseNode::Middle(ref sym) => sym.atom_type,
+            ParseNode::MathStrut     => AtomType::Alpha,
+            ParseNode::ColorSwitch(_) => AtomType::Transparent,
+            ParseNode::Not(_)        => AtomType::Relation,
+            ParseNode::Overline(_)   => AtomType::Alpha,
+            ParseNode::Underline(_)  => AtomType::Alpha,
+            ParseNode::Lap(_)        => AtomType::Alpha,
+            ParseNode::Transform(_, _) => AtomType::Alpha,
+            ParseNode::PoorMansBold(ref inner) => inner.first().map_or(AtomType::Alpha, ParseNode::atom_type),
+            ParseNode::OverUnder(ref ou) => ou.atom_type.unwrap_or_else(|| match ou.base.as_slice() {
+                [base] => base.atom_type(),
+                _ => AtomType::Alpha,
+            }),
+            ParseNode::Scale(ref sc) => sc.inner.first()
+                .map(|first| first.atom_type())
+                .unwrap_or(AtomType::Alpha),
             ParseNode::Stack(ref s)  => s.atom_type,
         }
     }
@@ -192,4 +380,46 @@ pub fn is_symbol(contents: &[ParseNode]) -> Option<Symbol> {
     }
 
     contents[0].is_symbol()
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+    use std::collections::hash_map::DefaultHasher;
+
+    fn hash_of<T: Hash>(value: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn tree(kern: f64) -> Vec<ParseNode> {
+        vec![
+            ParseNode::Scripts(Scripts {
+                base: Some(Box::new(ord('x'))),
+                superscript: Some(vec![ord('2')]),
+                subscript: None,
+            }),
+            ParseNode::Kerning(Unit::Em(kern)),
+            ParseNode::Scale(SizeChange { factor: kern, inner: vec![ord('y')] }),
+        ]
+    }
+
+    #[test]
+    fn equal_trees_hash_equal() {
+        assert_eq!(tree(0.5), tree(0.5));
+        assert_eq!(hash_of(&tree(0.5)), hash_of(&tree(0.5)));
+        assert_ne!(tree(0.5), tree(0.25));
+    }
+
+    #[test]
+    fn floats_compare_by_bits() {
+        // Every value is equal to itself, so that `Eq` holds, and equal
+        // values hash alike.
+        assert_eq!(tree(std::f64::NAN), tree(std::f64::NAN));
+        assert_eq!(hash_of(&tree(std::f64::NAN)), hash_of(&tree(std::f64::NAN)));
+        assert_eq!(Unit::Px(-0.0), Unit::Px(0.0));
+        assert_eq!(hash_of(&Unit::Px(-0.0)), hash_of(&Unit::Px(0.0)));
+        assert_ne!(Unit::Em(1.0), Unit::Px(1.0));
+    }
+}
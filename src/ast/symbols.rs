@@ -1,10 +1,18 @@
 use unicode_math::{SYMBOLS, AtomType};
+use std::hash::{Hash, Hasher};
+use std::mem;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Symbol {
     pub codepoint: char,
     pub atom_type: AtomType
 }
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.codepoint.hash(state);
+        mem::discriminant(&self.atom_type).hash(state);
+    }
+}
 impl Symbol {
     pub fn from_name(name: &str) -> Option<Self> {
         others(name).or_else(|| symbol(name))
@@ -99,6 +107,13 @@ fn others(name: &str) -> Option<Symbol> {
         "~" => Symbol { codepoint: '\u{303}', atom_type: AtomType::Accent }, // 771
         "." => Symbol { codepoint: '\u{307}', atom_type: AtomType::Accent }, // 775 
 
+        // Stretchy arrow accents shim
+        "overleftarrow" => Symbol { codepoint: '\u{20D6}', atom_type: AtomType::Accent }, // 8406
+        "overrightarrow" => Symbol { codepoint: '\u{20D7}', atom_type: AtomType::Accent }, // 8407
+        "overleftrightarrow" => Symbol { codepoint: '\u{20E1}', atom_type: AtomType::Accent }, // 8417
+        "overleftharpoon" => Symbol { codepoint: '\u{20D0}', atom_type: AtomType::Accent }, // 8400
+        "overrightharpoon" => Symbol { codepoint: '\u{20D1}', atom_type: AtomType::Accent }, // 8401
+
         // Binary operators shim
         "circ" => Symbol { codepoint: '\u{2218}', atom_type: AtomType::Binary }, // 8728
         "bullet" => Symbol { codepoint: '\u{2219}', atom_type: AtomType::Binary }, // 8729
@@ -109,6 +124,9 @@ fn others(name: &str) -> Option<Symbol> {
 
         // Misc symbols shim
         "|" => Symbol { codepoint: '\u{2016}', atom_type: AtomType::Fence }, // 8214
+
+        // Relations shim: a bar spaced as a relation, as in `\{x \mid y\}`
+        "mid" => Symbol { codepoint: '\u{2223}', atom_type: AtomType::Relation }, // 8739
         
         _ => return None
     };
@@ -3,6 +3,7 @@ use std::cmp::{Ord, Eq, PartialEq, PartialOrd, Ordering};
 use std::marker::PhantomData;
 use std::fmt;
 use std::iter::Sum;
+use std::hash::{Hash, Hasher};
 
 #[derive(Debug)]
 pub struct Length<U> {
@@ -16,9 +17,29 @@ impl<U> Length<U> {
     pub fn is_zero(&self) -> bool {
         self.value == 0.0
     }
+    pub fn is_finite(&self) -> bool {
+        self.value.is_finite()
+    }
     pub fn new(value: impl Into<f64>, unit: U) -> Self {
         Length { value: value.into(), _m: PhantomData }
     }
+    pub fn abs(self) -> Self {
+        Length { value: self.value.abs(), _m: PhantomData }
+    }
+    pub fn min(self, other: Self) -> Self {
+        Length { value: self.value.min(other.value), _m: PhantomData }
+    }
+    pub fn max(self, other: Self) -> Self {
+        Length { value: self.value.max(other.value), _m: PhantomData }
+    }
+    /// Restrict `self` to `[lo, hi]`.  If `lo > hi` the result is `lo`.
+    pub fn clamp(self, lo: Self, hi: Self) -> Self {
+        self.min(hi).max(lo)
+    }
+    /// Linear interpolation; `t = 0` yields `self` and `t = 1` yields `other`.
+    pub fn lerp(self, other: Self, t: f64) -> Self {
+        Length { value: self.value + (other.value - self.value) * t, _m: PhantomData }
+    }
 }
 impl<U> Clone for Length<U> {
     fn clone(&self) -> Self {
@@ -40,19 +61,29 @@ impl<U> Default for Length<U> {
 }
 impl<U> PartialEq for Length<U> {
     fn eq(&self, other: &Self) -> bool {
-        self.value.eq(&other.value)
+        self.cmp(other) == Ordering::Equal
     }
 }
 impl<U> PartialOrd for Length<U> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.value.partial_cmp(&other.value)
+        Some(self.cmp(other))
     }
 }
 
 impl<U> Eq for Length<U> {}
+impl<U> Hash for Length<U> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_f64(self.value, state)
+    }
+}
+/// Lengths are totally ordered: NaN is equal to itself and
+/// greater than every other value, including infinity.
 impl<U> Ord for Length<U> {
     fn cmp(&self, rhs: &Self) -> Ordering {
-        self.value.partial_cmp(&rhs.value).unwrap()
+        match self.value.partial_cmp(&rhs.value) {
+            Some(ordering) => ordering,
+            None => self.value.is_nan().cmp(&rhs.value.is_nan()),
+        }
     }
 }
 
@@ -167,8 +198,143 @@ impl<T, U, V> Div<Scale<V, U>> for Scale<T, U> {
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, Clone, Copy)]
 pub enum Unit {
     Em(f64),
     Px(f64)
 }
+
+impl PartialEq for Unit {
+    fn eq(&self, other: &Unit) -> bool {
+        match (*self, *other) {
+            (Unit::Em(a), Unit::Em(b)) |
+            (Unit::Px(a), Unit::Px(b)) => f64_bits(a) == f64_bits(b),
+            _ => false,
+        }
+    }
+}
+impl Eq for Unit {}
+impl Hash for Unit {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match *self {
+            Unit::Em(em) => { 0u8.hash(state); hash_f64(em, state) }
+            Unit::Px(px) => { 1u8.hash(state); hash_f64(px, state) }
+        }
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Unit::Em(em) => write!(f, "{}em", em),
+            Unit::Px(px) => write!(f, "{}px", px),
+        }
+    }
+}
+
+/// A 2x3 affine matrix, mapping `(x, y)` to
+/// `(xx * x + xy * y + tx, yx * x + yy * y + ty)`.  It acts on coordinates
+/// with the y-axis pointing up, relative to the origin of the transformed
+/// content on its baseline.
+#[derive(Debug, Clone, Copy)]
+pub struct AffineTransform {
+    pub xx: f64,
+    pub xy: f64,
+    pub yx: f64,
+    pub yy: f64,
+    pub tx: f64,
+    pub ty: f64,
+}
+
+impl AffineTransform {
+    pub fn identity() -> Self {
+        AffineTransform::scale(1.0, 1.0)
+    }
+    pub fn scale(sx: f64, sy: f64) -> Self {
+        AffineTransform { xx: sx, xy: 0.0, yx: 0.0, yy: sy, tx: 0.0, ty: 0.0 }
+    }
+    /// Slant by `angle` radians, leaning to the right for positive angles,
+    /// like an oblique font.
+    pub fn skew_x(angle: f64) -> Self {
+        AffineTransform { xy: angle.tan(), ..AffineTransform::identity() }
+    }
+    pub fn apply(&self, x: f64, y: f64) -> (f64, f64) {
+        (self.xx * x + self.xy * y + self.tx, self.yx * x + self.yy * y + self.ty)
+    }
+    /// The same transform, acting on coordinates with the y-axis pointing
+    /// down.
+    pub fn flip_y(self) -> Self {
+        AffineTransform { xy: -self.xy, yx: -self.yx, ty: -self.ty, ..self }
+    }
+    fn bits(&self) -> [u64; 6] {
+        [
+            f64_bits(self.xx), f64_bits(self.xy), f64_bits(self.yx),
+            f64_bits(self.yy), f64_bits(self.tx), f64_bits(self.ty),
+        ]
+    }
+}
+
+impl PartialEq for AffineTransform {
+    fn eq(&self, other: &AffineTransform) -> bool {
+        self.bits() == other.bits()
+    }
+}
+impl Eq for AffineTransform {}
+impl Hash for AffineTransform {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.bits().hash(state)
+    }
+}
+
+/// The bit pattern of a float, for comparing and hashing it.  Adding `0.0`
+/// folds `-0.0` into `0.0`, and all NaNs are mapped to the same pattern, so
+/// that every value is equal to itself.
+pub(crate) fn f64_bits(value: f64) -> u64 {
+    let value = if value.is_nan() { std::f64::NAN } else { value + 0.0 };
+    value.to_bits()
+}
+
+/// Hash a float by `f64_bits`.  Types which hash their floats this way
+/// must compare them by `f64_bits` as well.
+pub(crate) fn hash_f64<H: Hasher>(value: f64, state: &mut H) {
+    f64_bits(value).hash(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn px(value: f64) -> Length<Px> {
+        Length::new(value, Px)
+    }
+
+    #[test]
+    fn length_helpers() {
+        assert_eq!(px(-2.0).abs(), px(2.0));
+        assert_eq!(px(-2.0).min(px(1.0)), px(-2.0));
+        assert_eq!(px(-2.0).max(px(-3.0)), px(-2.0));
+
+        assert_eq!(px(-5.0).clamp(px(-1.0), px(1.0)), px(-1.0));
+        assert_eq!(px(5.0).clamp(px(-1.0), px(1.0)), px(1.0));
+        assert_eq!(px(0.5).clamp(px(-1.0), px(1.0)), px(0.5));
+        // An empty range yields its lower end.
+        assert_eq!(px(0.0).clamp(px(1.0), px(-1.0)), px(1.0));
+
+        assert_eq!(px(-2.0).lerp(px(2.0), 0.0), px(-2.0));
+        assert_eq!(px(-2.0).lerp(px(2.0), 1.0), px(2.0));
+        assert_eq!(px(-2.0).lerp(px(2.0), 0.25), px(-1.0));
+    }
+
+    #[test]
+    fn nan_is_ordered() {
+        let nan = px(std::f64::NAN);
+        assert_eq!(nan.cmp(&nan), Ordering::Equal);
+        assert!(nan > px(std::f64::INFINITY));
+
+        let mut lengths = vec![nan, px(1.0), px(std::f64::INFINITY), nan, px(-1.0)];
+        lengths.sort();
+        let values: Vec<f64> = lengths.iter().map(|&l| l / Px).collect();
+        assert_eq!(&values[..3], &[-1.0, 1.0, std::f64::INFINITY]);
+        assert!(values[3].is_nan() && values[4].is_nan());
+    }
+}
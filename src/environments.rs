@@ -2,6 +2,8 @@
 use crate::font::{Style, AtomType};
 use crate::ast::{self, ParseNode, symbols::Symbol};
 use crate::error::{ParseResult, ParseError};
+use crate::dimensions::Unit;
+use std::fmt;
 
 /// An enumeration of recognized enviornmnets.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -13,6 +15,7 @@ pub enum Environment {
     BbMatrix,
     VMatrix,
     VvMatrix,
+    Cases,
 }
 
 impl Environment {
@@ -26,6 +29,7 @@ impl Environment {
             "Bmatrix" => Some(Environment::BbMatrix),
             "vmatrix" => Some(Environment::VMatrix),
             "Vmatrix" => Some(Environment::VvMatrix),
+            "cases" => Some(Environment::Cases),
             _ => None,
         }
     }
@@ -34,7 +38,7 @@ impl Environment {
 /// The horizontal positioning of an array.  These are parsed as an optional
 /// argument for the Array environment. The default value is `Centered` along
 /// the x-axis.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ArrayVerticalAlign {
     /// Centered along the x-axis.
     Centered,
@@ -55,7 +59,7 @@ impl Default for ArrayVerticalAlign {
 // TODO: since we use default values, we should make the argument optional?
 /// Array column alignent.  These are parsed as a required macro argument
 /// for the array enviornment. The default value is `Centered`.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ArrayColumnAlign {
     /// Column is centered
     Centered,
@@ -75,7 +79,7 @@ impl Default for ArrayColumnAlign {
 
 /// Formatting options for a single column.  This includes both the horizontal
 /// alignment of the column (clr), and optional vertical bar spacers (on the left).
-#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ArraySingleColumnFormatting {
     /// The alignment of the column.  Defaults to Centered.
     alignment: ArrayColumnAlign,
@@ -87,7 +91,7 @@ pub struct ArraySingleColumnFormatting {
 /// The collection of column formatting for an array.  This includes the vertical
 /// alignment for each column in an array along with optional vertical bars
 /// positioned to the right of the last column.
-#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ArrayColumnsFormatting {
     /// The formatting specifications for each column
     columns: Vec<ArraySingleColumnFormatting>,
@@ -96,7 +100,7 @@ pub struct ArrayColumnsFormatting {
     right_vert: u8,
 }
 
-#[derive(Default, Debug, Clone, PartialEq)]
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Array {
     /// The formatting arguments (clr) for each row.  Default: center.
     pub col_format: ArrayColumnsFormatting,
@@ -104,12 +108,108 @@ pub struct Array {
     /// A collection of rows.  Each row consists of one `Vec<Expression>`.
     pub rows: Vec<Vec<Expression>>,
 
+    /// Additional vertical space after each row, as in `\\[2pt]`.
+    /// A `None` entry (or a missing one) keeps the default row separation.
+    pub row_gaps: Vec<Option<Unit>>,
+
     /// The left delimiter for the array (optional).
     pub left_delimiter: Option<Symbol>,
 
     /// The right delimiter for the array (optional).
     pub right_delimiter: Option<Symbol>,
+
+    /// The space between columns, if it differs from the default.
+    pub col_sep: Option<Unit>,
+
+    /// Leave out the null delimiter space on the right when there is no
+    /// right delimiter, as `cases` does.
+    pub omit_right_space: bool,
+}
+
+impl ArrayColumnsFormatting {
+    /// Columns with the given alignments and no vertical bars.
+    pub fn from_alignments(alignments: &[ArrayColumnAlign]) -> ArrayColumnsFormatting {
+        ArrayColumnsFormatting {
+            columns: alignments.iter()
+                .map(|&alignment| ArraySingleColumnFormatting { alignment, left_vert: 0 })
+                .collect(),
+            right_vert: 0,
+        }
+    }
+
+    /// The alignment of column `idx`.  Columns without a specification are centered.
+    pub fn alignment(&self, idx: usize) -> ArrayColumnAlign {
+        self.columns.get(idx).map_or(ArrayColumnAlign::Centered, |col| col.alignment)
+    }
+}
+
+impl Array {
+    /// The body of a `cases` environment: a left brace followed by two left
+    /// aligned columns, separated by a quad as in amsmath.  There is no
+    /// space after the last column.
+    pub fn cases(rows: Vec<Vec<Expression>>) -> Array {
+        Array {
+            col_format: ArrayColumnsFormatting::from_alignments(&[
+                ArrayColumnAlign::Left,
+                ArrayColumnAlign::Left,
+            ]),
+            rows,
+            left_delimiter: Some(Symbol { codepoint: '{', atom_type: AtomType::Open }),
+            col_sep: Some(Unit::Em(1.0)),
+            omit_right_space: true,
+            ..Array::default()
+        }
+    }
 }
 
 type Expression = Vec<ParseNode>;
 
+impl fmt::Display for ArrayColumnsFormatting {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for col in &self.columns {
+            for _ in 0..col.left_vert {
+                f.write_str("|")?;
+            }
+            f.write_str(match col.alignment {
+                ArrayColumnAlign::Centered => "c",
+                ArrayColumnAlign::Left => "l",
+                ArrayColumnAlign::Right => "r",
+            })?;
+        }
+        for _ in 0..self.right_vert {
+            f.write_str("|")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Array {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(left) = self.left_delimiter {
+            write!(f, "\\left{}", left)?;
+        }
+        write!(f, "\\begin{{array}}{{{}}}", self.col_format)?;
+        for (i, row) in self.rows.iter().enumerate() {
+            if i > 0 {
+                f.write_str("\\\\")?;
+                if let Some(&Some(gap)) = self.row_gaps.get(i - 1) {
+                    write!(f, "[{}]", gap)?;
+                }
+            }
+            for (j, cell) in row.iter().enumerate() {
+                if j > 0 {
+                    f.write_str("&")?;
+                }
+                for node in cell {
+                    write!(f, "{}", node)?;
+                }
+            }
+        }
+        f.write_str("\\end{array}")?;
+        if let Some(right) = self.right_delimiter {
+            write!(f, "\\right{}", right)?;
+        }
+        Ok(())
+    }
+}
+
@@ -8,7 +8,13 @@ pub type ParseResult<'a, T> = ::std::result::Result<T, ParseError<'a>>;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum LayoutError {
-    Font(FontError)
+    Font(FontError),
+    MaxDepthExceeded,
+    /// A superscript was attached to a base which already has one, as in
+    /// `x^y^z`.  A braced base, as in `{x^y}^z`, is fine.
+    ExcessiveSuperscripts,
+    /// A subscript was attached to a base which already has one.
+    ExcessiveSubscripts,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -14,6 +14,8 @@ pub use font::opentype::math::{
 
 use crate::dimensions::{*};
 use crate::error::FontError;
+use std::cell::RefCell;
+use std::collections::HashMap;
 
 pub type MathFont = OpenTypeFont;
 
@@ -23,31 +25,32 @@ pub struct FontContext<'f> {
     pub math: &'f MathHeader,
     pub constants: Constants,
     pub units_per_em: Scale<Font, Em>,
+    chars: RefCell<Option<HashMap<u16, char>>>,
 }
 impl<'f> FontContext<'f> {
     pub fn glyph(&self, codepoint: char) -> Result<Glyph<'f>, FontError> {
         use font::Font;
         let gid = self.font.gid_for_codepoint(codepoint as u32).ok_or(FontError::MissingGlyphCodepoint(codepoint))?;
-        self.glyph_from_gid(gid.0 as u16)
+        let mut glyph = self.glyph_from_gid(gid.0 as u16)?;
+        glyph.codepoint = Some(codepoint);
+        Ok(glyph)
     }
     pub fn glyph_from_gid(&self, gid: u16) -> Result<Glyph<'f>, FontError> {
         use font::{Font};
-        let font = self.font;
-        let hmetrics = font.glyph_metrics(gid).ok_or(FontError::MissingGlyphGID(gid))?;
-        let italics = self.math.glyph_info.italics_correction_info.get(gid).map(|info| info.value).unwrap_or_default();
-        let attachment = self.math.glyph_info.top_accent_attachment.get(gid).map(|info| info.value).unwrap_or_default();
-        let glyph = font.glyph(GlyphId(gid as u32)).ok_or(FontError::MissingGlyphGID(gid))?;
+        let metrics = self.glyph_metrics_only(gid)?;
+        let glyph = self.font.glyph(GlyphId(gid as u32)).ok_or(FontError::MissingGlyphGID(gid))?;
         let bbox = glyph.path.bounds();
         let ll = bbox.lower_left();
         let ur = bbox.upper_right();
 
         Ok(Glyph {
             gid,
+            codepoint: None,
             font: self.font,
-            advance: Length::new(hmetrics.advance, Font),
-            lsb: Length::new(hmetrics.lsb, Font),
-            italics: Length::new(italics, Font),
-            attachment: Length::new(attachment, Font),
+            advance: metrics.advance,
+            lsb: metrics.lsb,
+            italics: metrics.italics,
+            attachment: metrics.attachment,
             bbox: (
                 Length::new(ll.x(), Font),
                 Length::new(ur.y(), Font),
@@ -56,6 +59,45 @@ impl<'f> FontContext<'f> {
             )
         })
     }
+    /// The metrics of a glyph, without its ink bounds.  This is cheaper than
+    /// `glyph_from_gid`, which has to read the outline of the glyph.
+    pub fn glyph_metrics_only(&self, gid: u16) -> Result<GlyphMetrics, FontError> {
+        use font::{Font};
+        let hmetrics = self.font.glyph_metrics(gid).ok_or(FontError::MissingGlyphGID(gid))?;
+        let italics = self.math.glyph_info.italics_correction_info.get(gid).map(|info| info.value).unwrap_or_default();
+        let attachment = self.math.glyph_info.top_accent_attachment.get(gid).map(|info| info.value).unwrap_or_default();
+        Ok(GlyphMetrics {
+            gid,
+            advance: Length::new(hmetrics.advance, Font),
+            lsb: Length::new(hmetrics.lsb, Font),
+            italics: Length::new(italics, Font),
+            attachment: Length::new(attachment, Font),
+        })
+    }
+    /// The advance width of the glyph for `codepoint`.
+    pub fn advance(&self, codepoint: char) -> Result<Length<Em>, FontError> {
+        let (advance, _) = self.hmetrics(codepoint)?;
+        Ok(advance / self.units_per_em)
+    }
+    /// The left side bearing of the glyph for `codepoint`.
+    pub fn side_bearing(&self, codepoint: char) -> Result<Length<Em>, FontError> {
+        let (_, lsb) = self.hmetrics(codepoint)?;
+        Ok(lsb / self.units_per_em)
+    }
+    /// The kerning between two adjacent glyphs of a text run, from the
+    /// font's kern or GPOS pair adjustments.  Zero if the pair has none.
+    /// Scripts are kerned with the MATH table instead; see `kerning`.
+    pub fn pair_kern(&self, left: u16, right: u16) -> Length<Em> {
+        use font::Font;
+        let kern = self.font.kerning(GlyphId(left as u32), GlyphId(right as u32));
+        Length::new(kern as f64, Font) / self.units_per_em
+    }
+    fn hmetrics(&self, codepoint: char) -> Result<(Length<Font>, Length<Font>), FontError> {
+        use font::Font;
+        let GlyphId(gid) = self.font.gid_for_codepoint(codepoint as u32).ok_or(FontError::MissingGlyphCodepoint(codepoint))?;
+        let hmetrics = self.font.glyph_metrics(gid as u16).ok_or(FontError::MissingGlyphGID(gid as u16))?;
+        Ok((Length::new(hmetrics.advance, Font), Length::new(hmetrics.lsb, Font)))
+    }
     pub fn new(font: &'f MathFont) -> Self {
         use font::Font;
         let math = font.math.as_ref().expect("no MATH tables");
@@ -67,14 +109,68 @@ impl<'f> FontContext<'f> {
             font,
             math,
             units_per_em,
-            constants
+            constants,
+            chars: RefCell::new(None),
         }
     }
+    /// The codepoint which the font maps to `gid`, if any.  The reverse
+    /// cmap is built on first use.
+    pub fn codepoint_for_gid(&self, gid: u16) -> Option<char> {
+        self.chars
+            .borrow_mut()
+            .get_or_insert_with(|| reverse_cmap(self.font))
+            .get(&gid)
+            .cloned()
+    }
+    /// Look up the single substitution for `gid` under the GSUB feature `tag`.
+    /// Returns `None` if the font does not provide one.
+    pub fn substitute(&self, tag: &[u8; 4], gid: u16) -> Option<u16> {
+        let gsub = self.font.gsub.as_ref()?;
+        gsub.substitute_single(tag, gid)
+    }
     pub fn vert_variant(&self, codepoint: char, height: Length<Font>) -> Result<VariantGlyph, FontError> {
         use font::Font;
         let GlyphId(gid) = self.font.gid_for_codepoint(codepoint as u32).ok_or(FontError::MissingGlyphCodepoint(codepoint))?;
         Ok(self.math.variants.vert_variant(gid as u16, (height / Font) as u32))
     }
+    /// The `size`th vertical variant of the glyph for `codepoint` in the MATH
+    /// table, where size 0 is the glyph itself, as selected by `\big` and
+    /// friends.  Past the last variant, the glyph is assembled, 0.3em taller
+    /// for each further size.  A glyph which cannot be assembled stays at its
+    /// last variant.
+    pub fn vert_variant_sized(&self, codepoint: char, size: u8) -> Result<VariantGlyph, FontError> {
+        let step = Length::new(0.3, Em) * self.units_per_em;
+        let largest = self.vert_variant(codepoint, step * 1000.0)?;
+
+        let mut glyph = self.glyph(codepoint)?;
+        for n in 0..size {
+            if let VariantGlyph::Replacement(gid) = largest {
+                if gid == glyph.gid {
+                    break;
+                }
+            }
+
+            // `vert_variant` selects the first variant measured at least as
+            // tall as asked for.  The measure is close to the extent of the
+            // ink, but not always below it, so the request is grown until
+            // the next variant is returned.
+            let extent = glyph.height() - glyph.depth();
+            let mut request = extent + Length::new(1.0, Font);
+            let next = loop {
+                match self.vert_variant(codepoint, request)? {
+                    VariantGlyph::Replacement(gid) if gid == glyph.gid => request = request * 1.1,
+                    next => break next,
+                }
+            };
+            match next {
+                VariantGlyph::Replacement(gid) => glyph = self.glyph_from_gid(gid)?,
+                VariantGlyph::Constructable(..) => {
+                    return self.vert_variant(codepoint, extent + step * (size - n) as f64);
+                }
+            }
+        }
+        Ok(VariantGlyph::Replacement(glyph.gid))
+    }
     pub fn horz_variant(&self, codepoint: char, width: Length<Font>) -> Result<VariantGlyph, FontError> {
         use font::Font;
         let GlyphId(gid) = self.font.gid_for_codepoint(codepoint as u32).ok_or(FontError::MissingGlyphCodepoint(codepoint))?;
@@ -82,6 +178,29 @@ impl<'f> FontContext<'f> {
     }
 }
 
+/// Invert the cmap of `font`.  Only the BMP and SMP are searched, which
+/// contain all of the codepoints used in math typesetting.  If several
+/// codepoints map to the same glyph, the lowest one is kept.
+pub fn reverse_cmap(font: &MathFont) -> HashMap<u16, char> {
+    use font::Font;
+    let mut chars = HashMap::new();
+    for cp in (0..0x20000).filter_map(std::char::from_u32) {
+        if let Some(GlyphId(gid)) = font.gid_for_codepoint(cp as u32) {
+            chars.entry(gid as u16).or_insert(cp);
+        }
+    }
+    chars
+}
+
+/// The constants of the MATH table, in em.  Layout reads them from the
+/// `FontContext` of its `LayoutSettings`, never from the font itself, so
+/// individual values may be overridden on the context before layout:
+///
+/// ```ignore
+/// let mut ctx = FontContext::new(&font);
+/// ctx.constants.fraction_rule_thickness = Length::new(0.06, Em);
+/// let layout = engine::layout(&nodes, LayoutSettings::new(&ctx, 10.0, Style::Display))?;
+/// ```
 #[derive(Clone)]
 pub struct Constants {
     pub subscript_shift_down: Length<Em>,
@@ -111,6 +230,7 @@ pub struct Constants {
 
     pub axis_height: Length<Em>,
     pub accent_base_height: Length<Em>,
+    pub flattened_accent_base_height: Length<Em>,
 
     pub delimited_sub_formula_min_height: Length<Em>,
     pub display_operator_min_height: Length<Em>,
@@ -120,6 +240,13 @@ pub struct Constants {
     pub radical_rule_thickness: Length<Em>,
     pub radical_extra_ascender: Length<Em>,
 
+    pub overbar_vertical_gap: Length<Em>,
+    pub overbar_rule_thickness: Length<Em>,
+    pub overbar_extra_ascender: Length<Em>,
+    pub underbar_vertical_gap: Length<Em>,
+    pub underbar_rule_thickness: Length<Em>,
+    pub underbar_extra_descender: Length<Em>,
+
     pub stack_display_style_gap_min: Length<Em>,
     pub stack_top_display_style_shift_up: Length<Em>,
     pub stack_top_shift_up: Length<Em>,
@@ -166,6 +293,7 @@ impl Constants {
 
             axis_height: em(math.axis_height.value.into()),
             accent_base_height: em(math.accent_base_height.value.into()),
+            flattened_accent_base_height: em(math.flattened_accent_base_height.value.into()),
 
             delimited_sub_formula_min_height: em(math.delimited_sub_formula_min_height.into()),
 
@@ -176,6 +304,13 @@ impl Constants {
             radical_rule_thickness: em(math.radical_rule_thickness.value.into()),
             radical_extra_ascender: em(math.radical_extra_ascender.value.into()),
 
+            overbar_vertical_gap: em(math.overbar_vertical_gap.value.into()),
+            overbar_rule_thickness: em(math.overbar_rule_thickness.value.into()),
+            overbar_extra_ascender: em(math.overbar_extra_ascender.value.into()),
+            underbar_vertical_gap: em(math.underbar_vertical_gap.value.into()),
+            underbar_rule_thickness: em(math.underbar_rule_thickness.value.into()),
+            underbar_extra_descender: em(math.underbar_extra_descender.value.into()),
+
             stack_display_style_gap_min: em(math.stack_display_style_gap_min.value.into()),
             stack_top_display_style_shift_up: em(math.stack_top_display_style_shift_up.value.into()),
             stack_top_shift_up: em(math.stack_top_shift_up.value.into()),
@@ -195,6 +330,9 @@ impl Constants {
 pub struct Glyph<'f> {
     pub font: &'f MathFont,
     pub gid: u16,
+    /// The character this glyph was looked up by, if it was not selected
+    /// by glyph id, as variants and substitutions are.
+    pub codepoint: Option<char>,
     // x_min, y_min, x_max, y_max
     pub bbox: (Length<Font>, Length<Font>, Length<Font>, Length<Font>),
     pub advance: Length<Font>,
@@ -202,6 +340,16 @@ pub struct Glyph<'f> {
     pub italics: Length<Font>,
     pub attachment: Length<Font>,
 }
+/// The metrics of a `Glyph` which do not depend on its outline.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GlyphMetrics {
+    pub gid: u16,
+    pub advance: Length<Font>,
+    pub lsb: Length<Font>,
+    pub italics: Length<Font>,
+    pub attachment: Length<Font>,
+}
+
 impl<'f> Glyph<'f> {
     pub fn height(&self) -> Length<Font> {
         self.bbox.3
@@ -302,3 +450,60 @@ impl Default for Weight {
         Weight::None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    #[test]
+    fn advance_and_side_bearing() {
+        let ctx = ctx();
+        for &cp in &['x', 'W', ITALIC_F] {
+            let glyph = ctx.glyph(cp).unwrap();
+            assert_close(ctx.advance(cp).unwrap() / Em, (glyph.advance / ctx.units_per_em) / Em);
+            assert_close(ctx.side_bearing(cp).unwrap() / Em, (glyph.lsb / ctx.units_per_em) / Em);
+        }
+        assert!(ctx.advance('\u{10FFFD}').is_err());
+    }
+
+    #[test]
+    fn codepoint_round_trip() {
+        let ctx = ctx();
+        for &cp in &['x', 'A', '(', ITALIC_F] {
+            let gid = ctx.glyph(cp).unwrap().gid;
+            assert_eq!(ctx.codepoint_for_gid(gid), Some(cp));
+        }
+
+        // Size variants are not in the cmap.
+        let paren = ctx.glyph('(').unwrap().gid;
+        if let VariantGlyph::Replacement(gid) = ctx.vert_variant('(', Length::new(1500.0, Font)).unwrap() {
+            if gid != paren {
+                assert_eq!(ctx.codepoint_for_gid(gid), None);
+            }
+        }
+    }
+
+    #[test]
+    fn pair_kerning() {
+        let ctx = ctx();
+        let gid = |cp| ctx.glyph(cp).unwrap().gid;
+        assert!(ctx.pair_kern(gid('A'), gid('V')) < Length::zero());
+        // No adjustment between unrelated symbols.
+        assert!(ctx.pair_kern(gid('+'), gid('(')).is_zero());
+    }
+
+    #[test]
+    fn metrics_match_full_glyph() {
+        let ctx = ctx();
+        for &cp in &['x', 'W', '(', ITALIC_F] {
+            let glyph = ctx.glyph(cp).unwrap();
+            let metrics = ctx.glyph_metrics_only(glyph.gid).unwrap();
+            assert_eq!(metrics.advance, glyph.advance);
+            assert_eq!(metrics.lsb, glyph.lsb);
+            assert_eq!(metrics.italics, glyph.italics);
+            assert_eq!(metrics.attachment, glyph.attachment);
+        }
+        assert!(ctx.glyph_metrics_only(u16::max_value()).is_err());
+    }
+}
@@ -40,3 +40,194 @@ pub enum Command {
     TextOperator(&'static str, bool),
     SubStack(AtomType),
 }
+
+/// Set the symbols of `nodes` in bold italic, or bold for digits and upright
+/// symbols, as `\boldsymbol`.  Unlike `\pmb`, this selects the bold glyphs of
+/// the font, so it only affects symbols which have a bold variant.
+pub fn boldsymbol(nodes: &mut [ParseNode]) {
+    let style = Style::new().with_weight(Weight::BoldItalic);
+    for node in nodes {
+        match *node {
+            ParseNode::Symbol(ref mut sym) => sym.codepoint = style_symbol(sym.codepoint, style),
+            ParseNode::Scripts(ref mut scripts) => {
+                if let Some(ref mut base) = scripts.base {
+                    boldsymbol(std::slice::from_mut(&mut **base));
+                }
+            }
+            ParseNode::Group(ref mut inner) |
+            ParseNode::PoorMansBold(ref mut inner) => boldsymbol(inner),
+            ParseNode::AtomChange(ref mut ac) => boldsymbol(&mut ac.inner),
+            ParseNode::Color(ref mut clr) => boldsymbol(&mut clr.inner),
+            _ => (),
+        }
+    }
+}
+
+impl Command {
+    /// The fraction commands taking numerator and denominator as arguments.
+    /// `\dfrac` and `\tfrac` force display and text style, as do the
+    /// corresponding binomials.
+    pub fn fraction(name: &str) -> Option<Command> {
+        use self::MathStyle::*;
+        let (left, right, bar, style) = match name {
+            "frac" => (None, None, BarThickness::Default, NoChange),
+            "dfrac" => (None, None, BarThickness::Default, Display),
+            "tfrac" => (None, None, BarThickness::Default, Text),
+            "binom" => (sym!('(', open), sym!(')', close), BarThickness::None, NoChange),
+            "dbinom" => (sym!('(', open), sym!(')', close), BarThickness::None, Display),
+            "tbinom" => (sym!('(', open), sym!(')', close), BarThickness::None, Text),
+            _ => return None,
+        };
+        Some(Command::Fraction(left, right, bar, style))
+    }
+
+    /// The infix fraction commands, which take the material before
+    /// and after them in the current group as numerator and denominator.
+    pub fn infix(name: &str) -> Option<Command> {
+        match name {
+            "over" => Some(Command::Fraction(None, None, BarThickness::Default, MathStyle::NoChange)),
+            "atop" => Some(Command::Fraction(None, None, BarThickness::None, MathStyle::NoChange)),
+            "choose" => Some(Command::Fraction(sym!('(', open), sym!(')', close), BarThickness::None, MathStyle::NoChange)),
+            _ => None,
+        }
+    }
+
+    /// The named spaces, as kerns.  There is no `mu` unit, so these are
+    /// given in `em`, with 18mu to the em.
+    pub fn space(name: &str) -> Option<Command> {
+        let mu = match name {
+            "!" => -3.0,
+            "," => 3.0,
+            ":" | ">" => 4.0,
+            ";" => 5.0,
+            "quad" => 18.0,
+            "qquad" => 36.0,
+            _ => return None,
+        };
+        Some(Command::Kerning(Unit::Em(mu / 18.0)))
+    }
+
+    /// The style switches, which apply to the rest of the current group.
+    pub fn style(name: &str) -> Option<Command> {
+        let style = match name {
+            "displaystyle" => LayoutStyle::Display,
+            "textstyle" => LayoutStyle::Text,
+            "scriptstyle" => LayoutStyle::Script,
+            "scriptscriptstyle" => LayoutStyle::ScriptScript,
+            _ => return None,
+        };
+        Some(Command::Style(style))
+    }
+
+    /// The operators named by upright text, as `\sin`.  The flag is whether
+    /// scripts are set as limits in display style, as for `\lim`.
+    pub fn text_operator(name: &str) -> Option<Command> {
+        const OPERATORS: &[(&str, bool)] = &[
+            ("arccos", false), ("arcsin", false), ("arctan", false), ("arg", false),
+            ("cos", false), ("cosh", false), ("cot", false), ("coth", false),
+            ("csc", false), ("deg", false), ("det", true), ("dim", false),
+            ("exp", false), ("gcd", true), ("hom", false), ("inf", true),
+            ("ker", false), ("lg", false), ("lim", true), ("liminf", true),
+            ("limsup", true), ("ln", false), ("log", false), ("max", true),
+            ("min", true), ("Pr", true), ("sec", false), ("sin", false),
+            ("sinh", false), ("sup", true), ("tan", false), ("tanh", false),
+        ];
+        OPERATORS.iter()
+            .find(|&&(op, _)| op == name)
+            .map(|&(op, limits)| Command::TextOperator(op, limits))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dimensions::Px;
+    use crate::layout::engine;
+    use crate::render::{CountingBackend, Renderer};
+    use crate::testing::*;
+
+    #[test]
+    fn named_spaces() {
+        assert_eq!(Command::space("quad"), Some(Command::Kerning(Unit::Em(1.0))));
+        assert_eq!(Command::space(","), Some(Command::Kerning(Unit::Em(3.0 / 18.0))));
+        assert_eq!(Command::space("!"), Some(Command::Kerning(Unit::Em(-3.0 / 18.0))));
+        assert_eq!(Command::space("hspace"), None);
+
+        // A quad is as wide as the font size.
+        let quad = match Command::space("quad") {
+            Some(Command::Kerning(unit)) => ParseNode::Kerning(unit),
+            _ => unreachable!(),
+        };
+        let layout = engine::layout(&[quad], settings(LayoutStyle::Text)).unwrap();
+        assert_close(layout.width / Px, 10.0);
+    }
+
+    /// `\name{a}{b}`, for a fraction command `name`.
+    fn fraction(name: &str) -> ParseNode {
+        match Command::fraction(name) {
+            Some(Command::Fraction(left_delimiter, right_delimiter, bar_thickness, style)) => {
+                ParseNode::GenFraction(GenFraction {
+                    numerator: vec![ord('a')],
+                    denominator: vec![ord('b')],
+                    bar_thickness,
+                    left_delimiter,
+                    right_delimiter,
+                    style,
+                    bar_padding: Unit::Em(0.0),
+                })
+            }
+            _ => panic!("not a fraction command"),
+        }
+    }
+
+    #[test]
+    fn forced_fraction_styles() {
+        // The positions of the numerator and denominator.
+        let positions = |name, style| -> Vec<(f64, f64)> {
+            engine::layout(&[fraction(name)], settings(style)).unwrap()
+                .glyphs().map(|(pos, _)| (pos.x, pos.y)).collect()
+        };
+
+        assert_eq!(positions("tfrac", LayoutStyle::Display), positions("frac", LayoutStyle::Text));
+        assert_ne!(positions("frac", LayoutStyle::Display), positions("frac", LayoutStyle::Text));
+        assert_eq!(positions("dfrac", LayoutStyle::Script), positions("frac", LayoutStyle::Display));
+        assert_eq!(Command::fraction("tbinom"), Some(Command::Fraction(
+            sym!('(', open), sym!(')', close), BarThickness::None, MathStyle::Text
+        )));
+    }
+
+    #[test]
+    fn bold_symbols() {
+        let config = settings(LayoutStyle::Text);
+        let gid = |node: ParseNode| engine::layout(&[node], config).unwrap().glyphs().next().unwrap().1.gid;
+
+        let mut bold = [named("alpha")];
+        boldsymbol(&mut bold);
+        assert_ne!(gid(bold[0].clone()), gid(named("alpha")));
+
+        // `\pmb` overprints the glyph three times.
+        let pmb = engine::layout(&[ParseNode::PoorMansBold(vec![named("alpha")])], config).unwrap();
+        let mut out = CountingBackend::new();
+        Renderer::new().render(&pmb, &mut out);
+        assert_eq!(out.symbols, 3);
+        assert_close(pmb.width / Px, engine::layout(&[named("alpha")], config).unwrap().width / Px);
+    }
+
+    #[test]
+    fn style_switch_shrinks() {
+        assert_eq!(Command::style("textstyle"), Some(Command::Style(LayoutStyle::Text)));
+        assert_eq!(Command::style("scriptscriptstyle"), Some(Command::Style(LayoutStyle::ScriptScript)));
+        assert_eq!(Command::style("smallstyle"), None);
+
+        // `x \scriptstyle x`, in display style.
+        let script = match Command::style("scriptstyle") {
+            Some(Command::Style(style)) => ParseNode::Style(style),
+            _ => unreachable!(),
+        };
+        let layout = engine::layout(&[ord('x'), script, ord('x')], settings(LayoutStyle::Display)).unwrap();
+        let sizes: Vec<f64> = layout.glyphs().map(|(_, gly)| gly.size / Px).collect();
+        assert_eq!(sizes.len(), 2);
+        assert_close(sizes[0], 10.0);
+        assert!(sizes[1] < sizes[0]);
+    }
+}
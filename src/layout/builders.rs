@@ -1,5 +1,5 @@
 #![allow(dead_code)]
-use super::{VerticalBox, HorizontalBox, LayoutNode, LayoutVariant, Alignment, Grid, Layout, ColorChange};
+use super::{VerticalBox, HorizontalBox, LayoutNode, LayoutVariant, Alignment, Grid, GridBaseline, Layout, ColorChange};
 use std::cmp::{max, min};
 use crate::dimensions::*;
 use std::collections::BTreeMap;
@@ -35,10 +35,17 @@ impl<'a> VBox<'a> {
     }
 
     pub fn build(mut self) -> LayoutNode<'a> {
-        // The depth only depends on the depth
-        // of the last element and offset.
-        if let Some(node) = self.node.contents.last() {
-            self.depth = node.depth;
+        // The depth only depends on the depth of the last element and
+        // offset.  Trailing kerns have no depth of their own, so the last
+        // other element may still reach below them.
+        let mut below = Length::zero();
+        for (idx, node) in self.node.contents.iter().rev().enumerate() {
+            let depth = node.depth + below;
+            self.depth = if idx == 0 { depth } else { min(self.depth, depth) };
+            match node.node {
+                LayoutVariant::Kern => below += node.height,
+                _ => break,
+            }
         }
 
         self.depth -= self.node.offset;
@@ -118,49 +125,59 @@ impl<'a> Grid<'a> {
     pub fn new() -> Grid<'a> {
         Grid {
             contents: BTreeMap::new(),
-            rows: Vec::new(),
-            columns: Vec::new(),
+            rows: BTreeMap::new(),
+            columns: BTreeMap::new(),
         }
     }
     pub fn insert(&mut self, row: usize, column: usize, node: LayoutNode<'a>) {
-        if row >= self.rows.len() {
-            self.rows.resize(row + 1, (Length::zero(), Length::zero()));
+        let extent = self.rows.entry(row).or_insert((Length::zero(), Length::zero()));
+        if node.height > extent.0 {
+            extent.0 = node.height;
         }
-        if node.height > self.rows[row].0 {
-            self.rows[row].0 = node.height;
+        if node.depth < extent.1 {
+            extent.1 = node.depth;
         }
-        if node.depth < self.rows[row].1 {
-            self.rows[row].1 = node.depth;
-        }
-        if column >= self.columns.len() {
-            self.columns.resize(column + 1, Length::zero());
-        }
-        if node.width > self.columns[column] {
-            self.columns[column] = node.width;
+        let width = self.columns.entry(column).or_insert(Length::zero());
+        if node.width > *width {
+            *width = node.width;
         }
 
         self.contents.insert((row, column), node);
     }
     pub fn build(self) -> LayoutNode<'a> {
+        self.build_with_baseline(GridBaseline::Bottom)
+    }
+    /// Build the grid with its baseline at `baseline`, rather than at its
+    /// bottom edge.  The total height is the same either way.
+    pub fn build_with_baseline(self, baseline: GridBaseline) -> LayoutNode<'a> {
+        let total: Length<Px> = self.rows.values().map(|&(height, depth)| height - depth).sum();
+        let height = match baseline {
+            GridBaseline::Bottom => total,
+            GridBaseline::Center => total * 0.5,
+            GridBaseline::Row(row) => match self.rows.get(&row) {
+                Some(&(row_height, _)) => self.y_offsets()[&row] + row_height,
+                None => total,
+            },
+        };
         LayoutNode {
-            width:  self.columns.iter().cloned().sum(),
-            height: self.rows.iter().map(|&(height, depth)| height - depth).sum(),
-            depth: Length::zero(),
+            width:  self.columns.values().cloned().sum(),
+            height,
+            depth: height - total,
             node: LayoutVariant::Grid(self)
         }
     }
-    pub fn x_offsets(&self) -> Vec<Length<Px>> {
-        self.columns.iter().scan(Length::zero(), |acc, &width| {
+    pub fn x_offsets(&self) -> BTreeMap<usize, Length<Px>> {
+        self.columns.iter().scan(Length::zero(), |acc, (&idx, &width)| {
             let x = *acc;
             *acc += width;
-            Some(x)
+            Some((idx, x))
         }).collect()
     }
-    pub fn y_offsets(&self) -> Vec<Length<Px>> {
-        self.rows.iter().scan(Length::zero(), |acc, &(height, depth)| {
-            let x = *acc;
+    pub fn y_offsets(&self) -> BTreeMap<usize, Length<Px>> {
+        self.rows.iter().scan(Length::zero(), |acc, (&idx, &(height, depth))| {
+            let y = *acc;
             *acc += height - depth;
-            Some(x)
+            Some((idx, y))
         }).collect()
     }
 }
@@ -196,11 +213,18 @@ macro_rules! rule {
     );
 
     (width: $width:expr, height: $height:expr, depth: $depth:expr) => (
+        rule!(width: $width, height: $height, depth: $depth, style: RuleStyle::Solid)
+    );
+
+    (width: $width:expr, height: $height:expr, depth: $depth:expr, style: $style:expr) => (
         LayoutNode {
             width:  $width,
             height: $height,
             depth:  $depth,
-            node: LayoutVariant::Rule,
+            node: LayoutVariant::Rule(LayoutRule {
+                style: $style,
+                color: None,
+            }),
         }
     );
 }
@@ -225,6 +249,18 @@ macro_rules! kern {
     );
 }
 
+/// An empty box of zero width with the given height and depth.  Unlike a
+/// kern, it is never treated as spacing, so it counts as content wherever
+/// it is placed.
+pub fn strut<'a>(height: Length<Px>, depth: Length<Px>) -> LayoutNode<'a> {
+    LayoutNode {
+        width: Length::zero(),
+        height,
+        depth,
+        node: LayoutVariant::HorizontalBox(HorizontalBox::default()),
+    }
+}
+
 pub fn color<'a>(layout: Layout<'a>, color: &nodes::Color) -> LayoutNode<'a> {
     LayoutNode {
         width: layout.width,
@@ -236,3 +272,70 @@ pub fn color<'a>(layout: Layout<'a>, color: &nodes::Color) -> LayoutNode<'a> {
         }),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::{engine, Style};
+    use crate::testing::*;
+
+    #[test]
+    fn sparse_grid() {
+        let cell = |cp| engine::layout(&[ord(cp)], settings(Style::Text)).unwrap().as_node();
+        let (a, b) = (cell('a'), cell('g'));
+        let (a_width, a_height) = (a.width, a.height - a.depth);
+
+        let mut grid = Grid::new();
+        grid.insert(0, 0, a);
+        grid.insert(1000, 1000, b);
+        assert_eq!(grid.rows.len(), 2);
+        assert_eq!(grid.columns.len(), 2);
+
+        // Empty rows and columns in between take no space.
+        assert_eq!(grid.x_offsets()[&1000], a_width);
+        assert_eq!(grid.y_offsets()[&1000], a_height);
+    }
+
+    #[test]
+    fn grid_on_middle_row() {
+        let cell = || engine::layout(&[ord('g')], settings(Style::Text)).unwrap().as_node();
+        let (height, depth) = (cell().height, cell().depth);
+        let grid = || {
+            let mut grid = Grid::new();
+            for row in 0..3 {
+                grid.insert(row, 0, cell());
+            }
+            grid
+        };
+
+        let bottom = grid().build();
+        assert_eq!(bottom.height, (height - depth) * 3.0);
+        assert!(bottom.depth.is_zero());
+
+        // One row above the middle one, and the middle row's own height.
+        let middle = grid().build_with_baseline(GridBaseline::Row(1));
+        assert_eq!(middle.height, height - depth + height);
+        assert_eq!(middle.height - middle.depth, bottom.height);
+
+        let center = grid().build_with_baseline(GridBaseline::Center);
+        assert_eq!(center.height, -center.depth);
+
+        // A missing row leaves the grid on its bottom edge.
+        assert_eq!(grid().build_with_baseline(GridBaseline::Row(7)).height, bottom.height);
+    }
+
+    #[test]
+    fn trailing_kern_keeps_depth() {
+        let px = |value: f64| Length::new(value, Px);
+        let vbox = |kern| {
+            let mut vbox = VBox::new();
+            vbox.add_node(strut(px(8.0), px(-5.0)));
+            vbox.add_node(kern!(vert: px(kern)));
+            vbox.build()
+        };
+
+        // The strut still reaches below the kern.
+        assert_eq!(vbox(2.0).depth, px(-3.0));
+        assert!(vbox(7.0).depth.is_zero());
+    }
+}
@@ -6,7 +6,7 @@ use crate::layout::LayoutSettings;
 
 use super::{Style};
 use super::builders;
-use super::{LayoutNode, LayoutVariant, LayoutGlyph};
+use super::{LayoutNode, LayoutVariant, LayoutGlyph, LayoutRule, RuleStyle};
 use crate::ast::nodes::Rule;
 use crate::error::LayoutResult;
 
@@ -26,6 +26,7 @@ impl<'f> AsLayoutNode<'f> for Glyph<'f> {
             node:   LayoutVariant::Glyph(LayoutGlyph {
                 font: self.font,
                 gid: self.gid,
+                codepoint: self.codepoint,
                 size: Length::new(1.0, Em).scaled(config),
                 attachment: self.attachment.scaled(config),
                 italics: self.italics.scaled(config),
@@ -38,7 +39,10 @@ impl<'f> AsLayoutNode<'f> for Glyph<'f> {
 impl<'f> AsLayoutNode<'f> for Rule {
     fn as_layout<'a>(&self, config: LayoutSettings<'a, 'f>) -> LayoutResult<LayoutNode<'f>> {
         Ok(LayoutNode {
-            node:   LayoutVariant::Rule,
+            node:   LayoutVariant::Rule(LayoutRule {
+                style: RuleStyle::Solid,
+                color: self.color,
+            }),
             width:  self.width .scaled(config),
             height: self.height.scaled(config),
             depth:  Length::zero(),
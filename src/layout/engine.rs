@@ -2,7 +2,7 @@ use std::cmp::{min, max};
 
 use super::builders;
 use super::convert::AsLayoutNode;
-use super::{Alignment, Layout, LayoutNode, LayoutSettings, LayoutVariant, Style, ColorChange};
+use super::{Alignment, Layout, LayoutNode, LayoutSettings, LayoutVariant, Style, ColorChange, RuleStyle, LayoutRule, TransformBox};
 
 use crate::font::{
     kerning::{superscript_kern, subscript_kern},
@@ -11,23 +11,78 @@ use crate::font::{
 };
 use super::convert::Scaled;
 use super::spacing::{atom_space, Spacing};
-use crate::ast::nodes::{BarThickness, MathStyle, ParseNode, Accent, Delimited, GenFraction, Radical, Scripts, Stack};
+use crate::ast::nodes::{BarThickness, MathStyle, ParseNode, Accent, Delimited, Extensible, FixedDelimiter, GenFraction, LapSide, OverUnder, Radical, Scripts, Stack};
 use crate::ast::symbols::Symbol;
-use crate::environments::Array;
+use crate::environments::{Array, ArrayColumnAlign};
 use crate::dimensions::{*};
 use crate::layout;
-use crate::error::{LayoutResult, LayoutError};
+use crate::error::{Error, LayoutResult, LayoutError};
+use crate::render::Renderer;
 
 /// Entry point to our recursive algorithm
 pub fn layout<'a, 'f: 'a>(nodes: &[ParseNode], config: LayoutSettings<'a, 'f>) -> LayoutResult<Layout<'f>> {
-    layout_recurse(nodes, config, AtomType::Transparent)
+    layout_fragment(nodes, config, AtomType::Transparent, AtomType::Transparent)
+}
+
+/// Lay out `nodes` as part of a longer list, between atoms of type `prev`
+/// and `next`.  The space after `prev` is included at the start, and binary
+/// atoms at either end are treated as they would be within the whole list.
+/// The space before `next` is left to the following fragment, so fragments
+/// can be concatenated.  `layout` treats the list as self-contained, which is
+/// the same as passing `AtomType::Transparent` for both.
+pub fn layout_fragment<'a, 'f: 'a>(
+    nodes: &[ParseNode],
+    config: LayoutSettings<'a, 'f>,
+    prev: AtomType,
+    next: AtomType
+) -> LayoutResult<Layout<'f>> {
+    let mut layout = layout_recurse(nodes, config, prev, next)?;
+    layout.axis_height = config.ctx.constants.axis_height.scaled(config);
+    Ok(layout)
+}
+
+/// Lay out math set inline with text, as between `$...$`.  Large operators
+/// keep their text size and fractions are set in script style.
+///
+/// This, along with `layout_display`, is the usual entry point.  The style
+/// of `config` is replaced.
+pub fn layout_inline<'a, 'f: 'a>(nodes: &[ParseNode], mut config: LayoutSettings<'a, 'f>) -> LayoutResult<Layout<'f>> {
+    config.style = Style::Text;
+    layout(nodes, config)
+}
+
+/// Lay out math set on its own line, as between `$$...$$`.  Large operators
+/// use their display size and fractions are set in text style.
+///
+/// The style of `config` is replaced.
+pub fn layout_display<'a, 'f: 'a>(nodes: &[ParseNode], mut config: LayoutSettings<'a, 'f>) -> LayoutResult<Layout<'f>> {
+    config.style = Style::Display;
+    layout(nodes, config)
+}
+
+/// Lay out `nodes` and measure the result, as `Renderer::size` does:
+/// `(x0, y0, x1, y1)` relative to the baseline.  Any failure is reported as
+/// the crate-level `Error`, so callers need only this one function.
+pub fn layout_and_size<'a, 'f: 'a>(
+    nodes: &[ParseNode],
+    config: LayoutSettings<'a, 'f>
+) -> Result<(Layout<'f>, (f64, f64, f64, f64)), Error<'static>> {
+    let layout = layout(nodes, config)?;
+    let size = Renderer::new().size(&layout);
+    Ok((layout, size))
 }
 
 /// This method takes the parsing nodes and layouts them to layout nodes.
 #[allow(unconditional_recursion)]
-fn layout_recurse<'a, 'f: 'a>(nodes: &[ParseNode], mut config: LayoutSettings<'a, 'f>, parent_next: AtomType) -> LayoutResult<Layout<'f>> {
+fn layout_recurse<'a, 'f: 'a>(
+    nodes: &[ParseNode],
+    mut config: LayoutSettings<'a, 'f>,
+    parent_prev: AtomType,
+    parent_next: AtomType
+) -> LayoutResult<Layout<'f>> {
+    config = config.nested()?;
     let mut layout = Layout::new();
-    let mut prev = AtomType::Transparent;
+    let mut prev = parent_prev;
 
     for idx in 0..nodes.len() {
         let node = &nodes[idx];
@@ -41,7 +96,7 @@ fn layout_recurse<'a, 'f: 'a>(nodes: &[ParseNode], mut config: LayoutSettings<'a
         };
 
         let mut current = node.atom_type();
-        if current == AtomType::Binary {
+        if current == AtomType::Binary && config.reclassify_binary {
             if prev == AtomType::Transparent || prev == AtomType::Binary ||
                prev == AtomType::Relation || prev == AtomType::Open ||
                prev == AtomType::Punctuation {
@@ -60,20 +115,51 @@ fn layout_recurse<'a, 'f: 'a>(nodes: &[ParseNode], mut config: LayoutSettings<'a
             layout.add_node(kern!(horz: kern));
         }
 
+        let before = prev;
         prev = current;
         match *node {
             ParseNode::Style(sty) => config.style = sty,
+
+            // The color applies to the rest of the group, so the
+            // remaining siblings are laid out inside a color change.
+            // They are spaced against the atom before the switch.
+            ParseNode::ColorSwitch(color) => {
+                let inner = layout_recurse(&nodes[idx + 1..], config, before, parent_next)?;
+                layout.add_node(LayoutNode {
+                    width: inner.width,
+                    height: inner.height,
+                    depth: inner.depth,
+                    node: LayoutVariant::Color(ColorChange {
+                        color,
+                        inner: inner.contents,
+                    }),
+                });
+                break;
+            }
+
             _ => layout.dispatch(config.clone(), node, next)?,
         }
+
+        // A slanted symbol followed by an upright closing atom, such as `f)`,
+        // would otherwise collide.  Insert the italics correction between them.
+        if config.italic_correction && (next == AtomType::Close || next == AtomType::Punctuation) {
+            if let ParseNode::Symbol(_) = *node {
+                if let Some(gly) = layout.contents.last().and_then(LayoutNode::is_symbol) {
+                    if !gly.italics.is_zero() {
+                        layout.add_node(kern!(horz: gly.italics));
+                    }
+                }
+            }
+        }
     }
 
     Ok(layout.finalize())
 }
 
-fn layout_node<'a, 'f: 'a>(node: &ParseNode, config: LayoutSettings<'a, 'f>) -> Layout<'f> {
+fn layout_node<'a, 'f: 'a>(node: &ParseNode, config: LayoutSettings<'a, 'f>) -> LayoutResult<Layout<'f>> {
     let mut layout = Layout::new();
-    layout.dispatch(config, node, AtomType::Transparent);
-    layout.finalize()
+    layout.dispatch(config.nested()?, node, AtomType::Transparent)?;
+    Ok(layout.finalize())
 }
 
 impl<'f> Layout<'f> {
@@ -92,16 +178,62 @@ impl<'f> Layout<'f> {
             ParseNode::GenFraction(ref f) => self.frac(f, config)?,
             ParseNode::Stack(ref stack) => self.substack(stack, config)?,
             ParseNode::Array(ref arr) => self.array(arr, config)?,
+            ParseNode::VCenter(ref inner) => self.vcenter(inner, config)?,
+            ParseNode::Not(ref inner) => self.not(inner, config)?,
+            ParseNode::Overline(ref inner) => self.overline(inner, config)?,
+            ParseNode::Underline(ref inner) => self.underline(inner, config)?,
+            ParseNode::PoorMansBold(ref inner) => self.poor_mans_bold(inner, config)?,
+            ParseNode::Transform(transform, ref inner) => self.transform(transform, inner, config)?,
+            ParseNode::Extensible(ref ext) => self.extensible(ext, config)?,
+            ParseNode::FixedDelimiter(fd) => self.fixed_delimiter(fd, config)?,
+            // Outside of a `\left ... \right` group, `\middle` is not stretched.
+            ParseNode::Middle(sym) => match sym.codepoint {
+                '.' => self.add_node(kern!(horz: config.ctx.constants.null_delimiter_space.scaled(config))),
+                _ => self.symbol(sym, config)?,
+            },
+            ParseNode::OverUnder(ref ou) => self.over_under(ou, config)?,
+            // Handled by `layout_recurse`, which sees the following siblings.
+            ParseNode::ColorSwitch(_) => (),
+            ParseNode::MathStrut => {
+                // An invisible box with the height and depth of a parenthesis.
+                let paren = config.ctx.glyph('(')?;
+                self.add_node(builders::strut(paren.height().scaled(config), paren.depth().scaled(config)));
+            }
+            ParseNode::Lap(ref lap) => {
+                // The kerns cancel the width of the contents, so that
+                // only their height and depth remain.
+                let inner = layout(&lap.inner, config)?.as_node();
+                let width = inner.width;
+                self.add_node(match lap.side {
+                    LapSide::Right => hbox![inner, kern!(horz: -width)],
+                    LapSide::Left => hbox![kern!(horz: -width), inner],
+                    LapSide::Center => hbox![kern!(horz: -width * 0.5), inner, kern!(horz: -width * 0.5)],
+                })
+            }
+            ParseNode::RaiseBox(ref rb) => {
+                let inner = layout(&rb.inner, config)?.as_node();
+                self.add_node(vbox!(offset: -rb.amount.scaled(config); inner))
+            }
+            ParseNode::Extend(codepoint, width) => {
+                let width = width.scaled(config);
+                self.add_node(stretch_horz(codepoint, width, config)?)
+            }
 
             ParseNode::AtomChange(ref ac) => self.add_node(layout(&ac.inner, config)?.as_node()),
             ParseNode::Group(ref gp) => self.add_node(layout(gp, config)?.as_node()),
             ParseNode::Rule(rule) => self.add_node(rule.as_layout(config)?),
             ParseNode::Kerning(kern) => self.add_node(kern!(horz: kern.scaled(config))),
 
+            ParseNode::Scale(ref sc) => {
+                let inner = layout(&sc.inner, config.with_size_factor(sc.factor))?;
+                self.add_node(inner.as_node())
+            }
+
             ParseNode::Color(ref clr) => {
                 let inner = layout_recurse(
                     &clr.inner,
                     config,
+                    AtomType::Transparent,
                     next
                 )?;
                 self.add_node(builders::color(inner, clr))
@@ -114,7 +246,11 @@ impl<'f> Layout<'f> {
         Ok(())
     }
 
-    fn symbol<'a>(&mut self, sym: Symbol, config: LayoutSettings<'a, 'f>) -> LayoutResult<()> {
+    fn symbol<'a>(&mut self, mut sym: Symbol, config: LayoutSettings<'a, 'f>) -> LayoutResult<()> {
+        if let Some(codepoint) = config.glyph_mapper.and_then(|map| map(sym.codepoint, config.style)) {
+            sym.codepoint = codepoint;
+        }
+
         // Operators are handled specially.  We may need to find a larger
         // symbol and vertical center it.
         match sym.atom_type {
@@ -126,7 +262,7 @@ impl<'f> Layout<'f> {
 
     fn largeop<'a>(&mut self, sym: Symbol, config: LayoutSettings<'a, 'f>) -> LayoutResult<()> {
         let glyph = config.ctx.glyph(sym.codepoint)?;
-        if config.style > Style::Text {
+        if config.style > Style::Text || config.force_large_operators {
             let axis_offset = config.ctx.constants.axis_height.scaled(config);
             let largeop = config.ctx.vert_variant(
                 sym.codepoint, 
@@ -141,18 +277,41 @@ impl<'f> Layout<'f> {
     }
 
     fn accent<'a>(&mut self, acc: &Accent, config: LayoutSettings<'a, 'f>) -> LayoutResult<()> {
-        // [ ] The width of the selfing box is the width of the base.
+        // [x] The width of the selfing box is the width of the base.
         // [ ] Bottom accents: vertical placement is directly below nucleus,
         //       no correction takes place.
         // [ ] WideAccent vs Accent: Don't expand Accent types.
-        let base = layout(
+        let mut base = layout(
             &acc.nucleus,
             config.cramped()
         )?;
+
+        // An accented `i` or `j` loses its dot, provided the font
+        // has a dotless form (`dtls`).
+        if let Some(sym) = crate::ast::is_symbol(&acc.nucleus) {
+            if has_dot(sym.codepoint) {
+                let gid = config.ctx.glyph(sym.codepoint)?.gid;
+                if let Some(dotless) = config.ctx.substitute(b"dtls", gid) {
+                    base = Layout::new();
+                    base.add_node(config.ctx.glyph_from_gid(dotless)?.as_layout(config.cramped())?);
+                }
+            }
+        }
+
         let accent_variant = config.ctx.horz_variant(
             acc.symbol.codepoint,
             config.to_font(base.width)
         )?;
+
+        // Over tall bases, use the flattened form of the accent (`flac`) if
+        // the font provides one.
+        let flattened = config.ctx.constants.flattened_accent_base_height.scaled(config);
+        let accent_variant = match accent_variant {
+            VariantGlyph::Replacement(gid) if base.height > flattened => {
+                VariantGlyph::Replacement(config.ctx.substitute(b"flac", gid).unwrap_or(gid))
+            }
+            variant => variant,
+        };
         let accent = accent_variant.as_layout(config)?;
 
         // Attachment points for accent & base are calculated by
@@ -160,9 +319,12 @@ impl<'f> Layout<'f> {
         //   (b) Symbol:
         //      1. Attachment point (if there is one)
         //      2. Otherwise: (width + ic) / 2.0
+        let stretchy = is_stretchy_arrow(acc.symbol.codepoint);
         let base_offset = match layout::is_symbol(&base.contents) {
+            // Arrows span the base, so they are centered on its midpoint.
+            _ if stretchy => base.width * 0.5,
             Some(sym) => {
-                let glyph = config.ctx.glyph_from_gid(sym.gid)?;
+                let glyph = config.ctx.glyph_metrics_only(sym.gid)?;
                 if !glyph.attachment.is_zero() {
                     glyph.attachment.scaled(config)
                 } else {
@@ -174,6 +336,7 @@ impl<'f> Layout<'f> {
         };
 
         let acc_offset = match accent_variant {
+            _ if stretchy => accent.width * 0.5,
             VariantGlyph::Replacement(sym) => {
                 let glyph = config.ctx.glyph_from_gid(sym)?;
                 if !glyph.attachment.is_zero() {
@@ -186,16 +349,51 @@ impl<'f> Layout<'f> {
                 }
             }
 
-            VariantGlyph::Constructable(_, _) => accent.width * 0.5,
+            // The parts of a constructed accent need not be symmetric, so
+            // find its effective center from the parts themselves: the
+            // attachment point of the first part to have one, or otherwise
+            // the center of the middle part.
+            VariantGlyph::Constructable(_, ref parts) => {
+                let mut x = Length::zero();
+                let mut attachment = None;
+                let mut middle = None;
+                for (idx, instr) in parts.iter().enumerate() {
+                    let glyph = config.ctx.glyph_from_gid(instr.gid)?;
+                    x -= Length::new(instr.overlap, Font).scaled(config);
+                    if attachment.is_none() && !glyph.attachment.is_zero() {
+                        attachment = Some(x + glyph.attachment.scaled(config));
+                    }
+                    if parts.len() % 2 == 1 && idx == parts.len() / 2 {
+                        middle = Some(x + glyph.advance.scaled(config) * 0.5);
+                    }
+                    x += glyph.advance.scaled(config);
+                }
+                attachment.or(middle).unwrap_or(accent.width * 0.5)
+            }
         };
 
         // Do not place the accent any further than you would if given
-        // an `x` character in the current style.
-        let delta = -min(base.height, config.ctx.constants.accent_base_height.scaled(config));
+        // an `x` character in the current style.  If the nucleus is itself
+        // accented, rest the bottom of this accent on top of the inner one,
+        // separated by a rule's thickness, so that the two do not overlap.
+        let delta = match acc.nucleus.as_slice() {
+            [ParseNode::Accent(_)] => {
+                let gap = config.ctx.constants.fraction_rule_thickness.scaled(config);
+                gap - max(accent.depth, Length::zero())
+            }
+            _ => -min(base.height, config.ctx.constants.accent_base_height.scaled(config)),
+        };
+
+        // The width of the accented box is the width of the base, even
+        // if the accent overhangs it.
+        let mut accent_box = builders::HBox::new();
+        accent_box.add_node(kern!(horz: base_offset - acc_offset));
+        accent_box.add_node(accent);
+        accent_box.set_width(base.width);
 
         // By not placing an offset on this vbox, we are assured that the
         // baseline will match the baseline of `base.as_node()`
-        self.add_node(vbox!(hbox!(kern!(horz: base_offset - acc_offset), accent),
+        self.add_node(vbox!(accent_box.build(),
                             kern!(vert: delta),
                             base.as_node()));
         
@@ -203,70 +401,115 @@ impl<'f> Layout<'f> {
     }
 
     fn delimited<'a>(&mut self, delim: &Delimited, config: LayoutSettings<'a, 'f>) -> Result<(), LayoutError> {
-        let inner = layout(&delim.inner, config)?.as_node();
+        // `\middle` delimiters split the inner content into segments, and
+        // are sized along with the outer delimiters.
+        let mut segments = Vec::new();
+        let mut middles = Vec::new();
+        let mut start = 0;
+        for (idx, node) in delim.inner.iter().enumerate() {
+            if let ParseNode::Middle(sym) = *node {
+                segments.push(layout(&delim.inner[start..idx], config)?.as_node());
+                middles.push(sym);
+                start = idx + 1;
+            }
+        }
+        segments.push(layout(&delim.inner[start..], config)?.as_node());
+
+        // Like `\left.\right.` in TeX, no null delimiter space
+        // is added when both delimiters are empty.
+        if delim.left.codepoint == '.' && delim.right.codepoint == '.' && middles.is_empty() {
+            self.add_node(segments.pop().unwrap());
+            return Ok(());
+        }
 
-        let min_height = config.ctx.constants.delimited_sub_formula_min_height * config.font_size;
-        let null_delimiter_space = config.ctx.constants.null_delimiter_space * config.font_size;
+        let min_height = config.ctx.constants.delimited_sub_formula_min_height.scaled(config);
+        let null_delimiter_space = config.ctx.constants.null_delimiter_space.scaled(config);
+        let axis = config.ctx.constants.axis_height.scaled(config);
+
+        // The delimiters are centered on the axis, so they must extend as far
+        // above and below it as the furthest extreme of the inner content.
+        let height = segments.iter().map(|s| s.height).max().unwrap_or_default();
+        let depth = segments.iter().map(|s| s.depth).min().unwrap_or_default();
+        let extent = max(height - axis, axis - depth);
 
         // Only extend if we meet a certain size
         // TODO: This quick height check doesn't seem to be strong enough,
         // reference: http://tug.org/pipermail/luatex/2010-July/001745.html
-        if max(inner.height, -inner.depth) > min_height * 0.5 {
-            let axis = config.ctx.constants.axis_height * config.font_size;
-
-            let clearance = max(inner.height - axis, axis - inner.depth) * 2.0;
-            let clearance = max(
-                clearance * config.ctx.constants.delimiter_factor,
-                inner.height - inner.depth - config.ctx.constants.delimiter_short_fall * config.font_size
-            );
-            let clearance = config.to_font(clearance);
-
-            let left = match delim.left.codepoint {
-                '.' => kern!(horz: null_delimiter_space),
-                _ => {
-                    config.ctx.vert_variant(
-                        delim.left.codepoint,
-                        clearance
-                    )?.as_layout(config)?.centered(axis)
-                }
-            };
+        let extend = extent > min_height * 0.5;
+        let clearance = extent * 2.0;
+        let clearance = max(
+            clearance * config.ctx.constants.delimiter_factor,
+            clearance - config.ctx.constants.delimiter_short_fall.scaled(config)
+        );
+        let clearance = config.to_font(clearance);
 
-            let right = match delim.right.codepoint {
+        // An empty delimiter, including `\middle.`, is only a null space.
+        let delimiter = |sym: Symbol| -> LayoutResult<LayoutNode<'f>> {
+            Ok(match sym.codepoint {
                 '.' => kern!(horz: null_delimiter_space),
-                _ => {
-                    config.ctx.vert_variant(delim.right.codepoint, clearance)?
+                _ if extend => {
+                    delimiter_variant(sym.codepoint, clearance, config)?
                         .as_layout(config)?
                         .centered(axis)
                 }
-            };
+                _ => config.ctx.glyph(sym.codepoint)?.as_layout(config)?,
+            })
+        };
 
-            self.add_node(left);
-            self.add_node(inner);
-            self.add_node(right);
-        } else {
-            let left = match delim.left.codepoint {
-                '.' => kern!(horz: null_delimiter_space),
-                _ => config.ctx.glyph(delim.left.codepoint)?.as_layout(config)?,
-            };
+        self.add_node(delimiter(delim.left)?);
+        let mut segments = segments.into_iter();
+        self.add_node(segments.next().unwrap());
+        for (middle, segment) in middles.into_iter().zip(segments) {
+            self.add_node(delimiter(middle)?);
+            self.add_node(segment);
+        }
+        self.add_node(delimiter(delim.right)?);
 
-            let right = match delim.right.codepoint {
-                '.' => kern!(horz: null_delimiter_space),
-                _ => config.ctx.glyph(delim.right.codepoint)?.as_layout(config)?,
-            };
+        Ok(())
+    }
 
-            self.add_node(left);
-            self.add_node(inner);
-            self.add_node(right);
+    fn fixed_delimiter<'a>(&mut self, fd: FixedDelimiter, config: LayoutSettings<'a, 'f>) -> LayoutResult<()> {
+        if fd.symbol.codepoint == '.' {
+            self.add_node(kern!(horz: config.ctx.constants.null_delimiter_space.scaled(config)));
+            return Ok(());
+        }
+        if fd.size == 0 {
+            return self.symbol(fd.symbol, config);
         }
 
+        // The size selects a variant of the delimiter from the MATH table,
+        // regardless of the content it encloses.
+        let axis = config.ctx.constants.axis_height.scaled(config);
+        let delim = config.ctx.vert_variant_sized(fd.symbol.codepoint, fd.size)?
+            .as_layout(config)?
+            .centered(axis);
+        self.add_node(delim);
         Ok(())
     }
 
     fn scripts<'a>(&mut self, scripts: &Scripts, config: LayoutSettings<'a, 'f>) -> Result<(), LayoutError> {
         // See: https://tug.org/TUGboat/tb27-1/tb86jackowski.pdf
         //      https://www.tug.org/tugboat/tb30-1/tb94vieth.pdf
+
+        // Don't bother constructing a new node if there is nothing.
+        let is_empty = |script: &Option<Vec<ParseNode>>| script.as_ref().map_or(true, Vec::is_empty);
+
+        // Like TeX, refuse a double superscript or subscript, since which
+        // script belongs to which is ambiguous.
+        if let Some(ParseNode::Scripts(ref inner)) = scripts.base.as_ref().map(|b| &**b) {
+            if inner.superscript.is_some() && scripts.superscript.is_some() {
+                return Err(LayoutError::ExcessiveSuperscripts);
+            }
+            if inner.subscript.is_some() && scripts.subscript.is_some() {
+                return Err(LayoutError::ExcessiveSubscripts);
+            }
+        }
+        if scripts.base.is_none() && is_empty(&scripts.superscript) && is_empty(&scripts.subscript) {
+            return Ok(());
+        }
+
         let base = match scripts.base {
-            Some(ref base) => layout_node(base, config),
+            Some(ref base) => layout_node(base, config)?,
             None => Layout::new(),
         };
 
@@ -281,14 +524,31 @@ impl<'f> Layout<'f> {
         };
 
         // We use a different algoirthm for handling scripts for operators with limits.
-        // This is where he handle Operators with limits.
+        // This is where he handle Operators with limits.  Text operators, such
+        // as `\lim`, only take limits in display style, unless given `\limits`.
         if let Some(ref b) = scripts.base {
-            if AtomType::Operator(true) == b.atom_type() {
-                self.operator_limits(base, sup, sub, config);
+            let limits = match **b {
+                ParseNode::AtomChange(ref ac) if ac.display_limits => config.style > Style::Text,
+                _ => true,
+            };
+            if AtomType::Operator(true) == b.atom_type() && limits {
+                let sup = scripts.superscript.as_ref().map(|_| sup);
+                let sub = scripts.subscript.as_ref().map(|_| sub);
+                self.stack_limits(base, sup, sub, false, config);
                 return Ok(());
             }
         }
 
+        // Scripts on a `\left ... \right` group attach to the closing
+        // delimiter, rather than to the extent of the group as a whole.
+        let (base_height, base_depth) = match scripts.base.as_ref().map(|b| &**b) {
+            Some(&ParseNode::Delimited(ref delim)) if delim.right.codepoint != '.' => {
+                base.contents.last()
+                    .map_or((base.height, base.depth), |right| (right.height, right.depth))
+            }
+            _ => (base.height, base.depth),
+        };
+
         // We calculate the vertical positions of the scripts.  The `adjust_up`
         // variable will describe how far we need to adjust the superscript up.
         let mut adjust_up = Length::zero();
@@ -298,14 +558,10 @@ impl<'f> Layout<'f> {
 
         if scripts.superscript.is_some() {
             // Use default font values for first iteration of vertical height.
-            adjust_up = match config.style.is_cramped() {
-                true => config.ctx.constants.superscript_shift_up_cramped,
-                false => config.ctx.constants.superscript_shift_up,
-            }
-            .scaled(config);
+            adjust_up = config.style.sup_shift_up(config).scaled(config);
 
             // TODO: These checks should be recursive?
-            let mut height = base.height;
+            let mut height = base_height;
             if let Some(ref b) = scripts.base {
                 if b.atom_type() != AtomType::Operator(false) {
                     // For accents whose base is a simple symbol we do not take
@@ -331,6 +587,22 @@ impl<'f> Layout<'f> {
                             sup_kern = base_sym.italics;
                         }
                     }
+                    // A group ending in a slanted glyph, as `{f}`, is
+                    // corrected for that glyph.
+                    else if let ParseNode::Group(_) = **b {
+                        if let Some(last) = base.last_glyph() {
+                            sup_kern = last.italics;
+                        }
+                    }
+                }
+            }
+
+            // See `nolimitsmode` below.
+            if let Some(ref b) = scripts.base {
+                if let Some(base_sym) = base.is_symbol() {
+                    if AtomType::Operator(false) == b.atom_type() && config.nolimits_mode == 2 {
+                        sup_kern = config.ctx.glyph_metrics_only(base_sym.gid)?.italics.scaled(config) * 0.5;
+                    }
                 }
             }
 
@@ -346,7 +618,7 @@ impl<'f> Layout<'f> {
             // Use default font values for first iteration of vertical height.
             adjust_down = max!(config.ctx.constants.subscript_shift_down.scaled(config),
                                 sub.height - config.ctx.constants.subscript_top_max.scaled(config),
-                                config.ctx.constants.subscript_baseline_drop_min.scaled(config) - base.depth);
+                                config.ctx.constants.subscript_baseline_drop_min.scaled(config) - base_depth);
 
             // Provided that the base and subscript are symbols, we apply
             // kerning values found in the kerning font table
@@ -355,7 +627,12 @@ impl<'f> Layout<'f> {
                     if AtomType::Operator(false) == b.atom_type() {
                         // This recently changed in LuaTeX.  See `nolimitsmode`.
                         // This needs to be the glyph information _after_ layout for base.
-                        sub_kern = -config.ctx.glyph_from_gid(base_sym.gid)?.italics.scaled(config);
+                        let italics = config.ctx.glyph_metrics_only(base_sym.gid)?.italics.scaled(config);
+                        sub_kern = match config.nolimits_mode {
+                            1 => Length::zero(),
+                            2 => -italics * 0.5,
+                            _ => -italics,
+                        };
                     }
                 }
 
@@ -410,13 +687,17 @@ impl<'f> Layout<'f> {
         Ok(())
     }
 
-    fn operator_limits<'a>(
+    /// Stack `over` and `under` centered above and below `base`, as the
+    /// limits of an operator.  Other labelled constructions use this as
+    /// well, so that their spacing agrees with operator limits.
+    fn stack_limits<'a>(
         &mut self,
         base: Layout<'f>,
-        sup: Layout<'f>,
-        sub: Layout<'f>,
+        over: Option<Layout<'f>>,
+        under: Option<Layout<'f>>,
+        under_brace: bool,
         config: LayoutSettings<'a, 'f>
-    ) -> Result<(), LayoutError> {
+    ) {
         // Provided that the operator is a simple symbol, we need to account
         // for the italics correction of the symbol.  This how we "center"
         // the superscript and subscript of the limits.
@@ -425,51 +706,60 @@ impl<'f> Layout<'f> {
             None => Length::zero()
         };
 
-        // Next we calculate the kerning required to separate the superscript
-        // and subscript (respectively) from the base.
-        let sup_kern = max(
-            config.ctx.constants.upper_limit_baseline_rise_min.scaled(config),
-            config.ctx.constants.upper_limit_gap_min.scaled(config) - sup.depth
-        );
-        let sub_kern = max(
-            config.ctx.constants.lower_limit_gap_min.scaled(config),
-            config.ctx.constants.lower_limit_baseline_drop_min.scaled(config) - sub.height
-        ) - base.depth;
-
-        // We need to preserve the baseline of the operator when
-        // attaching the scripts.  Since the base should already
-        // be aligned, we only need to offset by the addition of
-        // subscripts.
-        let offset = sub.height + sub_kern;
-
         // We will construct a vbox containing the superscript/base/subscript.
         // We will all of these nodes, so we widen each to the largest.
         let width = max!(
             base.width,
-            sub.width + delta * 0.5,
-            sup.width + delta * 0.5
+            under.as_ref().map_or(Length::zero(), |sub| sub.width + delta * 0.5),
+            over.as_ref().map_or(Length::zero(), |sup| sup.width + delta * 0.5)
         );
 
-        self.add_node(vbox![
-            offset: offset;
-            hbox![align: Alignment::Centered(sup.width);
+        let mut vbox = builders::VBox::new();
+        if let Some(sup) = over {
+            // The kerning required to separate the superscript from the base.
+            let sup_kern = max(
+                config.ctx.constants.upper_limit_baseline_rise_min.scaled(config),
+                config.ctx.constants.upper_limit_gap_min.scaled(config) - sup.depth
+            );
+            let sup_width = sup.width;
+            vbox.add_node(hbox![align: Alignment::Centered(sup_width);
                 width: width;
                 kern![horz: delta * 0.5],
                 sup.as_node()
-            ],
-
-            kern!(vert: sup_kern),
-            base.centered(width).as_node(),
-            kern!(vert: sub_kern),
+            ]);
+            vbox.add_node(kern!(vert: sup_kern));
+        }
 
-            hbox![align: Alignment::Centered(sub.width);
+        let base_depth = base.depth;
+        vbox.add_node(base.centered(width).as_node());
+
+        if let Some(sub) = under {
+            // A label under a brace sits a fixed gap below the bottom of
+            // the brace, however deep its assembly is.  The baseline drop
+            // is only meaningful for the limits of an operator.
+            let sub_kern = match under_brace {
+                true => config.ctx.constants.lower_limit_gap_min.scaled(config),
+                false => max(
+                    config.ctx.constants.lower_limit_gap_min.scaled(config),
+                    config.ctx.constants.lower_limit_baseline_drop_min.scaled(config) - sub.height
+                ),
+            } - base_depth;
+
+            // We need to preserve the baseline of the operator when
+            // attaching the scripts.  Since the base should already
+            // be aligned, we only need to offset by the addition of
+            // subscripts.
+            let (sub_width, sub_height) = (sub.width, sub.height);
+            vbox.add_node(kern!(vert: sub_kern));
+            vbox.add_node(hbox![align: Alignment::Centered(sub_width);
                 width: width;
                 kern![horz: -delta * 0.5],
                 sub.as_node()
-            ]
-        ]);
-        
-        Ok(())
+            ]);
+            vbox.set_offset(sub_height + sub_kern);
+        }
+
+        self.add_node(vbox.build());
     }
 
     fn frac<'a>(&mut self, frac: &GenFraction, config: LayoutSettings<'a, 'f>) -> Result<(), LayoutError> {
@@ -484,6 +774,12 @@ impl<'f> Layout<'f> {
             BarThickness::None => Length::zero(),
             BarThickness::Unit(u) => u.scaled(config),
         };
+        // A negative thickness draws no bar, and a very thin one is
+        // thickened so that it does not vanish.
+        let bar = match bar {
+            bar if bar <= Length::zero() => Length::zero(),
+            bar => max(bar, config.min_bar_thickness),
+        };
 
         let mut n = layout(
             &frac.numerator,
@@ -494,12 +790,17 @@ impl<'f> Layout<'f> {
             config.denominator()
         )?;
 
-        if n.width > d.width {
-            d.alignment = Alignment::Centered(d.width);
-            d.width = n.width;
-        } else {
+        let padding = frac.bar_padding.scaled(config);
+        // Both parts are widened to the bar, which spans the wider of the two.
+        // Delimiters are added beside this box, so they do not affect it.
+        let width = max(n.width, d.width) + padding * 2.0;
+        if n.width < width {
             n.alignment = Alignment::Centered(n.width);
-            n.width = d.width;
+            n.width = width;
+        }
+        if d.width < width {
+            d.alignment = Alignment::Centered(d.width);
+            d.width = width;
         }
 
         let numer = n.as_node();
@@ -525,22 +826,35 @@ impl<'f> Layout<'f> {
 
         let kern_num = max(shift_up - axis - bar * 0.5, gap_num - numer.depth);
         let kern_den = max(shift_down + axis - denom.height - bar * 0.5, gap_denom);
+        // The offset is how far the baseline of the denominator lies below
+        // the baseline of the fraction.  A vbox takes its depth from its last
+        // node, less the offset, so the depth of the fraction is exactly the
+        // descender of the denominator below the new baseline.
         let offset = denom.height + kern_den + bar * 0.5 - axis;
 
-        let width = numer.width;
-        let inner = vbox!(offset: offset;
-            numer,
-            kern!(vert: kern_num),
-            rule!(width: width, height: bar),
-            kern!(vert: kern_den),
-            denom
-        );
+        // Without a bar, as in `\atop`, the gap between numerator and
+        // denominator remains, but no rule is emitted.
+        let inner = if bar.is_zero() {
+            vbox!(offset: offset;
+                numer,
+                kern!(vert: kern_num + kern_den),
+                denom
+            )
+        } else {
+            vbox!(offset: offset;
+                numer,
+                kern!(vert: kern_num),
+                rule!(width: width, height: bar),
+                kern!(vert: kern_den),
+                denom
+            )
+        };
 
-        let null_delimiter_space = config.ctx.constants.null_delimiter_space * config.font_size;
-        let axis_height = config.ctx.constants.axis_height * config.font_size;
+        let null_delimiter_space = config.ctx.constants.null_delimiter_space.scaled(config);
+        let axis_height = config.ctx.constants.axis_height.scaled(config);
         // Enclose fraction with delimiters if provided, otherwise with a NULL_DELIMITER_SPACE.
         let left = match frac.left_delimiter {
-            None => kern!(horz: null_delimiter_space),
+            None | Some(Symbol { codepoint: '.', .. }) => kern!(horz: null_delimiter_space),
             Some(sym) => {
                 let clearance = max(
                     inner.height - axis_height, 
@@ -548,19 +862,19 @@ impl<'f> Layout<'f> {
                 ) * 2.0;
                 let clearance = max(
                     clearance,
-                    config.ctx.constants.delimited_sub_formula_min_height * config.font_size
+                    config.ctx.constants.delimited_sub_formula_min_height.scaled(config)
                 );
 
                 config.ctx.vert_variant(
                     sym.codepoint, config.to_font(clearance)
                 )?
                 .as_layout(config)?
-                .centered(axis_height.scaled(config))
+                .centered(axis_height)
             }
         };
 
         let right = match frac.right_delimiter {
-            None => kern!(horz: null_delimiter_space),
+            None | Some(Symbol { codepoint: '.', .. }) => kern!(horz: null_delimiter_space),
             Some(sym) => {
                 let clearance = max(
                     inner.height - axis_height,
@@ -568,14 +882,14 @@ impl<'f> Layout<'f> {
                 ) * 2.0;
                 let clearance = max(
                     clearance, 
-                    config.ctx.constants.delimited_sub_formula_min_height * config.font_size
+                    config.ctx.constants.delimited_sub_formula_min_height.scaled(config)
                 );
 
                 config.ctx.vert_variant(
                     sym.codepoint, config.to_font(clearance)
                 )?
                 .as_layout(config)?
-                .centered(axis_height.scaled(config))
+                .centered(axis_height)
             }
         };
 
@@ -588,7 +902,13 @@ impl<'f> Layout<'f> {
 
     fn radical<'a>(&mut self, rad: &Radical, config: LayoutSettings<'a, 'f>) -> Result<(), LayoutError> {
         // reference rule 11 from pg 443 of TeXBook
-        let contents = layout(&rad.inner, config.cramped())?.as_node();
+        let mut contents = layout(&rad.inner, config.cramped())?.as_node();
+
+        // An empty radicand is given the height of an `x`, so that the
+        // surd is still sized as it would be for a short symbol.
+        if rad.inner.is_empty() {
+            contents = hbox![kern!(vert: config.ctx.constants.accent_base_height.scaled(config))];
+        }
 
         // obtain minimum clearange between radicand and radical bar
         // and cache other sizes that will be needed
@@ -651,12 +971,16 @@ impl<'f> Layout<'f> {
             lines.push(line);
         }
 
-        // Center lines according to widest variant
+        // Align lines according to widest variant
         for (n, line) in lines.iter_mut().enumerate() {
             if n == widest_idx {
                 continue;
             }
-            line.alignment = Alignment::Centered(line.width);
+            line.alignment = match stack.alignment {
+                ArrayColumnAlign::Centered => Alignment::Centered(line.width),
+                ArrayColumnAlign::Left => Alignment::Left,
+                ArrayColumnAlign::Right => Alignment::Right(line.width),
+            };
             line.width = widest;
         }
 
@@ -690,7 +1014,7 @@ impl<'f> Layout<'f> {
 
             // Try for an ideal gap, otherwise use the minimum
             if idx < length {
-                let gap = max(gap_min, gap_try - prev);
+                let gap = max(gap_min, gap_try - prev) + extra_gap(&stack.line_gaps, idx, config);
                 vbox.add_node(kern![vert: gap]);
             }
         }
@@ -706,6 +1030,191 @@ impl<'f> Layout<'f> {
         Ok(())
     }
 
+    fn extensible<'a>(&mut self, ext: &Extensible, config: LayoutSettings<'a, 'f>) -> Result<(), LayoutError> {
+        let over = layout(&ext.over, config.superscript_variant())?;
+        let under = layout(&ext.under, config.subscript_variant())?;
+
+        // Pad the labels on either side, so that they do not
+        // run into the ends of the relation.
+        let padding = Spacing::Thick.to_length().scaled(config) * 2.0;
+        let width = max(over.width, under.width) + padding;
+        let relation = stretch_horz(ext.symbol.codepoint, width, config)?;
+        let width = max(width, relation.width);
+
+        let gap_over = config.ctx.constants.upper_limit_gap_min.scaled(config) - over.depth;
+        let gap_under = config.ctx.constants.lower_limit_gap_min.scaled(config) - relation.depth;
+
+        // Preserve the baseline of the relation, as in `stack_limits`.
+        let offset = under.height + gap_under;
+        let (over_width, under_width) = (over.width, under.width);
+        self.add_node(vbox![
+            offset: offset;
+            hbox![align: Alignment::Centered(over_width);
+                width: width;
+                over.as_node()
+            ],
+            kern!(vert: gap_over),
+            hbox![align: Alignment::Centered(relation.width);
+                width: width;
+                relation
+            ],
+            kern!(vert: gap_under),
+            hbox![align: Alignment::Centered(under_width);
+                width: width;
+                under.as_node()
+            ]
+        ]);
+        Ok(())
+    }
+
+    fn over_under<'a>(&mut self, ou: &OverUnder, config: LayoutSettings<'a, 'f>) -> Result<(), LayoutError> {
+        let base = layout(&ou.base, config)?;
+        let over = match ou.over.is_empty() {
+            true => None,
+            false => Some(layout(&ou.over, config.superscript_variant())?),
+        };
+        let under = match ou.under.is_empty() {
+            true => None,
+            false => Some(layout(&ou.under, config.subscript_variant())?),
+        };
+        let under_brace = match ou.base[..] {
+            [ParseNode::Extend(codepoint, _)] => is_bottom_brace(codepoint),
+            _ => false,
+        };
+        self.stack_limits(base, over, under, under_brace, config);
+        Ok(())
+    }
+
+    fn not<'a>(&mut self, inner: &[ParseNode], config: LayoutSettings<'a, 'f>) -> LayoutResult<()> {
+        // A single relation is replaced by its negated form, if the font has
+        // one.  Otherwise, including for stretched relations, a slash is
+        // overlaid on the center of the box.
+        if let Some(sym) = crate::ast::is_symbol(inner) {
+            if let Some(negated) = negated_relation(sym.codepoint) {
+                if let Ok(glyph) = config.ctx.glyph(negated) {
+                    self.add_node(glyph.as_layout(config)?);
+                    return Ok(());
+                }
+            }
+        }
+
+        let inner = layout(inner, config)?;
+        let slash = config.ctx.glyph('/')?.as_layout(config)?;
+        let (width, slash_width) = (inner.width, slash.width);
+        let center = (inner.height + inner.depth) * 0.5;
+        let slash_center = (slash.height + slash.depth) * 0.5;
+
+        self.add_node(inner.as_node());
+        self.add_node(kern!(horz: -(width + slash_width) * 0.5));
+        self.add_node(vbox!(offset: slash_center - center; slash));
+        self.add_node(kern!(horz: (width - slash_width) * 0.5));
+        Ok(())
+    }
+
+    // The bars span the whole of the laid out contents, so that scripts
+    // in `\overline{x^2}` are covered as well as the base.
+    fn overline<'a>(&mut self, inner: &[ParseNode], config: LayoutSettings<'a, 'f>) -> LayoutResult<()> {
+        let inner = layout(inner, config.cramped())?.as_node();
+        let gap = config.ctx.constants.overbar_vertical_gap.scaled(config);
+        let thickness = config.ctx.constants.overbar_rule_thickness.scaled(config);
+        let ascender = config.ctx.constants.overbar_extra_ascender.scaled(config);
+
+        let width = inner.width;
+        self.add_node(vbox![
+            kern!(vert: ascender),
+            rule!(width: width, height: thickness),
+            kern!(vert: gap),
+            inner
+        ]);
+        Ok(())
+    }
+
+    fn underline<'a>(&mut self, inner: &[ParseNode], config: LayoutSettings<'a, 'f>) -> LayoutResult<()> {
+        let inner = layout(inner, config)?.as_node();
+        let gap = config.ctx.constants.underbar_vertical_gap.scaled(config) - inner.depth;
+        let thickness = config.ctx.constants.underbar_rule_thickness.scaled(config);
+        let descender = config.ctx.constants.underbar_extra_descender.scaled(config);
+
+        // Keep the baseline of the contents.
+        let width = inner.width;
+        self.add_node(vbox![
+            offset: gap + thickness + descender;
+            inner,
+            kern!(vert: gap),
+            rule!(width: width, height: thickness),
+            kern!(vert: descender)
+        ]);
+        Ok(())
+    }
+
+    fn transform<'a>(&mut self, transform: AffineTransform, inner: &[ParseNode], config: LayoutSettings<'a, 'f>) -> LayoutResult<()> {
+        let inner = layout(inner, config)?.as_node();
+
+        // The box of the result bounds the transformed corners of the box
+        // of the contents.
+        let (width, height, depth) = (inner.width / Px, inner.height / Px, inner.depth / Px);
+        let corners = [(0.0, height), (width, height), (0.0, depth), (width, depth)];
+        let (mut x0, mut y0, mut x1, mut y1) = (f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for &(x, y) in &corners {
+            let (x, y) = transform.apply(x, y);
+            x0 = x0.min(x);
+            y0 = y0.min(y);
+            x1 = x1.max(x);
+            y1 = y1.max(y);
+        }
+
+        self.add_node(LayoutNode {
+            width: Length::new(x1 - x0, Px),
+            height: Length::new(y1, Px),
+            depth: Length::new(y0.min(0.0), Px),
+            node: LayoutVariant::Transform(TransformBox {
+                transform: AffineTransform { tx: transform.tx - x0, ..transform },
+                inner: Box::new(inner),
+            }),
+        });
+        Ok(())
+    }
+
+    fn poor_mans_bold<'a>(&mut self, inner: &[ParseNode], config: LayoutSettings<'a, 'f>) -> LayoutResult<()> {
+        // The second copy is shifted right, and the third is raised half
+        // way between the two.  The final kern returns to the end of the
+        // first copy, so the box keeps the natural width of its contents.
+        let shift = Length::new(0.025, Em).scaled(config);
+        let raise = Length::new(0.02, Em).scaled(config);
+
+        let inner = layout(inner, config)?.as_node();
+        let width = inner.width;
+        self.add_node(hbox![
+            inner.clone(),
+            kern!(horz: shift - width),
+            inner.clone(),
+            kern!(horz: -shift * 0.5 - width),
+            vbox!(offset: -raise; inner),
+            kern!(horz: -shift * 0.5)
+        ]);
+        Ok(())
+    }
+
+    fn vcenter<'a>(
+        &mut self,
+        inner: &[ParseNode],
+        config: LayoutSettings<'a, 'f>
+    ) -> Result<(), LayoutError> {
+        let inner = layout(inner, config)?.as_node();
+
+        // Vertically center the contents to the axis
+        let offset = {
+            (inner.height + inner.depth)
+            * 0.5
+            - config.ctx.constants.axis_height.scaled(config)
+        };
+        let mut vbox = builders::VBox::new();
+        vbox.add_node(inner);
+        vbox.set_offset(offset);
+        self.add_node(vbox.build());
+        Ok(())
+    }
+
     fn array<'a>(
         &mut self,
         array: &Array,
@@ -714,12 +1223,19 @@ impl<'f> Layout<'f> {
         // TODO: let jot = UNITS_PER_EM / 4;
 
         // \strutbox height = 0.7\baseline
-        let strut_height = Length::new(0.7, Em) * config.font_size;
+        let strut_height = Length::new(0.7, Em) * config.font_size * config.array_stretch;
 
         // \strutbox depth  = 0.3\baseline
-        let strut_depth = Length::new(0.3, Em) * config.font_size;
-        let row_sep = Length::new(0.25, Em) * config.font_size;
-        let column_sep = Length::new(5.0 / 12.0, Em) * config.font_size;
+        let strut_depth = Length::new(0.3, Em) * config.font_size * config.array_stretch;
+        let row_sep = Length::new(0.25, Em) * config.font_size * config.array_stretch;
+        let column_sep = match array.col_sep {
+            Some(sep) => sep.scaled(config),
+            None => Length::new(5.0 / 12.0, Em) * config.font_size * config.array_col_sep,
+        };
+
+        // A `.` delimiter is no delimiter at all.
+        let left_delimiter = array.left_delimiter.filter(|sym| sym.codepoint != '.');
+        let right_delimiter = array.right_delimiter.filter(|sym| sym.codepoint != '.');
 
         // Don't bother constructing a new node if there is nothing.
         let num_rows = array.rows.len();
@@ -769,7 +1285,7 @@ impl<'f> Layout<'f> {
 
         // If there are no delimiters, insert a null space.  Otherwise we insert
         // the delimiters _after_ we have laidout the body of the matrix.
-        if array.left_delimiter.is_none() {
+        if left_delimiter.is_none() {
             hbox.add_node(kern![horz: config.ctx.constants.null_delimiter_space * config.font_size]);
         }
 
@@ -777,9 +1293,13 @@ impl<'f> Layout<'f> {
         for (col_idx, col) in columns.into_iter().enumerate() {
             let mut vbox = builders::VBox::new();
             for (row_idx, mut row) in col.into_iter().enumerate() {
-                // Center columns as necessary
+                // Align columns as necessary
                 if row.width < col_widths[col_idx] {
-                    row.alignment = Alignment::Centered(row.width);
+                    row.alignment = match array.col_format.alignment(col_idx) {
+                        ArrayColumnAlign::Centered => Alignment::Centered(row.width),
+                        ArrayColumnAlign::Left => Alignment::Left,
+                        ArrayColumnAlign::Right => Alignment::Right(row.width),
+                    };
                     row.width = col_widths[col_idx];
                 }
 
@@ -794,13 +1314,14 @@ impl<'f> Layout<'f> {
                 // the row_seperation.
                 // FIXME: This should be actual depth, not additional kerning
                 let node = row.as_node();
+                let extra = extra_gap(&array.row_gaps, row_idx, config);
                 if row_idx + 1 == num_rows {
                     let depth = max(-node.depth, row_sep);
                     vbox.add_node(node);
-                    vbox.add_node(kern![vert: depth]);
+                    vbox.add_node(kern![vert: depth + extra]);
                 } else {
                     vbox.add_node(node);
-                    vbox.add_node(kern![vert: row_sep]);
+                    vbox.add_node(kern![vert: row_sep + extra]);
                 }
             }
 
@@ -811,7 +1332,9 @@ impl<'f> Layout<'f> {
             }
         }
 
-        if array.right_delimiter.is_none() {
+        // A one-sided array, such as `cases`, may leave out the space on
+        // its open side.
+        if right_delimiter.is_none() && !array.omit_right_space {
             hbox.add_node(kern![horz: config.ctx.constants.null_delimiter_space * config.font_size]);
         }
 
@@ -827,33 +1350,1036 @@ impl<'f> Layout<'f> {
 
         // Now that we know the layout of the matrix body we can place scaled delimiters
         // First check if there are any delimiters to add, if not just return.
-        if array.left_delimiter.is_none() && array.right_delimiter.is_none() {
+        if left_delimiter.is_none() && right_delimiter.is_none() {
             self.add_node(vbox);
             return Ok(());
         }
 
-        // place delimiters in an hbox surrounding the matrix body
+        // place delimiters in an hbox surrounding the matrix body,
+        // centered on its vertical center.
         let mut hbox = builders::HBox::new();
-        let axis = config.ctx.constants.axis_height.scaled(config);
+        let center = height * 0.5 - offset;
         let clearance = max(height * config.ctx.constants.delimiter_factor,
                             height - config.ctx.constants.delimiter_short_fall * config.font_size);
 
-        if let Some(left) = array.left_delimiter {
+        if let Some(left) = left_delimiter {
             let left = config.ctx.vert_variant(
                 left.codepoint, config.to_font(clearance)
-            )?.as_layout(config)?.centered(axis);
+            )?.as_layout(config)?.centered(center);
             hbox.add_node(left);
         }
 
         hbox.add_node(vbox);
-        if let Some(right) = array.right_delimiter {
+        if let Some(right) = right_delimiter {
             let right = config.ctx.vert_variant(
                 right.codepoint, config.to_font(clearance)
-            )?.as_layout(config)?.centered(axis);
+            )?.as_layout(config)?.centered(center);
             hbox.add_node(right);
         }
         self.add_node(hbox.build());
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// The variant of the delimiter `codepoint` covering `clearance`.  With
+/// `prefer_fixed_delimiters`, a single glyph is chosen over an assembly if it
+/// is no more than `delimiter_short_fall` too small.
+fn delimiter_variant(codepoint: char, clearance: Length<Font>, config: LayoutSettings) -> LayoutResult<VariantGlyph> {
+    let variant = config.ctx.vert_variant(codepoint, clearance)?;
+    if let VariantGlyph::Constructable(..) = variant {
+        if config.prefer_fixed_delimiters {
+            let short_fall = config.to_font(config.ctx.constants.delimiter_short_fall.scaled(config));
+            let fixed = config.ctx.vert_variant(codepoint, clearance - short_fall)?;
+            if let VariantGlyph::Replacement(_) = fixed {
+                return Ok(fixed);
+            }
+        }
+    }
+    Ok(variant)
+}
+
+/// The additional space requested after row `idx` (e.g. `\\[2pt]`), if any.
+fn extra_gap(gaps: &[Option<Unit>], idx: usize, config: LayoutSettings) -> Length<Px> {
+    match gaps.get(idx) {
+        Some(&Some(gap)) => gap.scaled(config),
+        _ => Length::zero(),
+    }
+}
+
+/// Letters which have a dotless form to be used under accents.
+fn has_dot(codepoint: char) -> bool {
+    match codepoint {
+        'i' | 'j' |
+        '\u{1D422}' | '\u{1D423}' | // bold
+        '\u{1D456}' | '\u{1D457}' | // italic
+        '\u{1D48A}' | '\u{1D48B}'   // bold italic
+            => true,
+        _ => false,
+    }
+}
+
+/// Arrows and harpoons which stretch over the whole of their base.
+/// If the font has no construction for them, `horz_variant` falls back
+/// to the widest fixed size.
+fn is_stretchy_arrow(codepoint: char) -> bool {
+    match codepoint {
+        '\u{20D0}' | '\u{20D1}' | // harpoons
+        '\u{20D6}' | '\u{20D7}' | // left and right arrows
+        '\u{20E1}'                // left right arrow
+            => true,
+        _ => false,
+    }
+}
+
+/// The precomposed negation of a relation, as `≠` for `=`.
+fn negated_relation(codepoint: char) -> Option<char> {
+    Some(match codepoint {
+        '=' => '≠',
+        '<' => '≮',
+        '>' => '≯',
+        '≤' => '≰',
+        '≥' => '≱',
+        '≡' => '≢',
+        '∼' => '≁',
+        '≃' => '≄',
+        '≅' => '≇',
+        '≈' => '≉',
+        '∈' => '∉',
+        '∋' => '∌',
+        '⊂' => '⊄',
+        '⊃' => '⊅',
+        '⊆' => '⊈',
+        '⊇' => '⊉',
+        '∣' => '∤',
+        '∥' => '∦',
+        _ => return None,
+    })
+}
+
+/// Braces, brackets and parentheses which open upwards, and are set below
+/// their base, as in `\underbrace`.
+fn is_bottom_brace(codepoint: char) -> bool {
+    match codepoint {
+        '\u{23B5}' | // bottom square bracket
+        '\u{23DD}' | // bottom parenthesis
+        '\u{23DF}'   // bottom curly bracket
+            => true,
+        _ => false,
+    }
+}
+
+/// Stretch `codepoint` horizontally to at least `width`.  If the font has
+/// no variant which is wide enough, the glyph is repeated with overlap.
+fn stretch_horz<'a, 'f>(codepoint: char, width: Length<Px>, config: LayoutSettings<'a, 'f>) -> LayoutResult<LayoutNode<'f>> {
+    let node = config.ctx.horz_variant(codepoint, config.to_font(width))?.as_layout(config)?;
+    if node.width >= width || node.width.is_zero() {
+        return Ok(node);
+    }
+
+    let count = (width / Px / (node.width / Px)).ceil() as usize;
+    let overlap = (node.width * count as f64 - width) * (1.0 / (count - 1) as f64);
+    let mut hbox = builders::HBox::new();
+    for n in 0..count {
+        if n > 0 {
+            hbox.add_node(kern!(horz: -overlap));
+        }
+        hbox.add_node(node.clone());
+    }
+    Ok(hbox.build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::nodes::{Lap, RaiseBox, SizeChange};
+    use crate::error::FontError;
+    use crate::render::CountingBackend;
+    use crate::testing::*;
+
+    #[test]
+    fn italic_correction_before_close() {
+        let nodes = [ord(ITALIC_F), sym(')', AtomType::Close)];
+        let config = settings(Style::Text);
+        let plain = layout(&nodes, config).unwrap();
+        let corrected = layout(&nodes, config.with_italic_correction(true)).unwrap();
+
+        let italics = config.ctx.glyph(ITALIC_F).unwrap().italics.scaled(config);
+        assert!(!italics.is_zero());
+        assert_close((corrected.width - plain.width) / Px, italics / Px);
+    }
+
+    /// The distance from the top to the bottom of a layout.
+    fn extent(layout: &Layout) -> f64 {
+        (layout.height - layout.depth) / Px
+    }
+
+    #[test]
+    fn row_gap_adds_space() {
+        let array = |gap| ParseNode::Array(Array {
+            rows: vec![vec![vec![ord('a')]], vec![vec![ord('b')]]],
+            row_gaps: vec![gap],
+            ..Array::default()
+        });
+        let stack = |gap| ParseNode::Stack(Stack {
+            atom_type: AtomType::Alpha,
+            lines: vec![vec![ord('a')], vec![ord('b')]],
+            line_gaps: vec![gap],
+            alignment: ArrayColumnAlign::Centered,
+        });
+
+        let gap = Some(Unit::Px(10.0));
+        let config = settings(Style::Text);
+        for (plain, gapped) in vec![(array(None), array(gap)), (stack(None), stack(gap))] {
+            let plain = layout(&[plain], config).unwrap();
+            let gapped = layout(&[gapped], config).unwrap();
+            assert_close(extent(&gapped) - extent(&plain), 10.0);
+        }
+    }
+
+    /// The ids of the glyphs of `layout`, in the order they are drawn.
+    fn gids(layout: &Layout) -> Vec<u16> {
+        layout.glyphs().map(|(_, gly)| gly.gid).collect()
+    }
+
+    fn rule(width: f64, height: f64) -> ParseNode {
+        ParseNode::Rule(crate::ast::nodes::Rule {
+            width: Unit::Em(width),
+            height: Unit::Em(height),
+            color: None,
+        })
+    }
+
+    fn accent(name: &str, nucleus: Vec<ParseNode>) -> ParseNode {
+        ParseNode::Accent(Accent {
+            symbol: Symbol::from_name(name).unwrap(),
+            nucleus,
+        })
+    }
+
+    #[test]
+    fn accent_flattened_over_tall_base() {
+        let config = settings(Style::Display);
+        let hat = |base| accent("^", vec![base]);
+
+        // Bases of the same width select the same variant of the accent,
+        // which is then flattened only over the tall base, if the font
+        // has a flattened form.
+        let short = gids(&layout(&[hat(rule(0.5, 0.1))], config).unwrap());
+        let tall = gids(&layout(&[hat(rule(0.5, 2.0))], config).unwrap());
+        let flattened = config.ctx.substitute(b"flac", short[0]).unwrap_or(short[0]);
+        assert_eq!(tall, vec![flattened]);
+    }
+
+    #[test]
+    fn dotless_base_under_accent() {
+        let config = settings(Style::Display);
+        let i = config.ctx.glyph('\u{1D456}').unwrap().gid;
+        let accented = gids(&layout(&[accent("^", vec![ord('\u{1D456}')])], config).unwrap());
+        match config.ctx.substitute(b"dtls", i) {
+            Some(dotless) => {
+                assert!(accented.contains(&dotless));
+                assert!(!accented.contains(&i));
+            }
+            None => assert!(accented.contains(&i)),
+        }
+    }
+
+    fn frac(numerator: Vec<ParseNode>, denominator: Vec<ParseNode>) -> ParseNode {
+        ParseNode::GenFraction(GenFraction {
+            numerator,
+            denominator,
+            bar_thickness: BarThickness::Default,
+            left_delimiter: None,
+            right_delimiter: None,
+            style: MathStyle::NoChange,
+            bar_padding: Unit::Em(0.0),
+        })
+    }
+
+    fn parens(inner: Vec<ParseNode>) -> ParseNode {
+        ParseNode::Delimited(Delimited {
+            left: Symbol { codepoint: '(', atom_type: AtomType::Open },
+            right: Symbol { codepoint: ')', atom_type: AtomType::Close },
+            inner,
+        })
+    }
+
+    #[test]
+    fn delimiters_stretch_in_script_style() {
+        let config = settings(Style::Script);
+        let inner = frac(vec![frac(vec![ord('a')], vec![ord('b')])], vec![ord('c')]);
+        let delimited = layout(&[parens(vec![inner])], config).unwrap();
+
+        let left = &delimited.contents[0];
+        let paren = config.ctx.glyph('(').unwrap().as_layout(config).unwrap();
+        assert!(left.height - left.depth > paren.height - paren.depth);
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        let nested = |depth| (0..depth).fold(ord('x'), |inner, _| ParseNode::Group(vec![inner]));
+        let config = settings(Style::Text).with_max_depth(10);
+        assert!(layout(&[nested(5)], config).is_ok());
+        assert_eq!(layout(&[nested(20)], config).unwrap_err(), LayoutError::MaxDepthExceeded);
+    }
+
+    #[test]
+    fn nested_accents_do_not_overlap() {
+        let config = settings(Style::Display);
+        let nested = layout(&[accent("^", vec![accent("^", vec![ord('x')])])], config).unwrap();
+
+        // The top and bottom of the ink of each glyph, in y-down coordinates.
+        let ink: Vec<(f64, f64)> = nested.glyphs()
+            .map(|(pos, gly)| {
+                let glyph = config.ctx.glyph_from_gid(gly.gid).unwrap();
+                (pos.y - glyph.height().scaled(config) / Px, pos.y - glyph.depth().scaled(config) / Px)
+            })
+            .collect();
+        let (outer, inner) = (ink[0], ink[1]);
+        assert!(outer.1 < inner.0);
+    }
+
+    #[test]
+    fn delimiters_cover_asymmetric_content() {
+        let config = settings(Style::Text);
+        let delimited = layout(&[parens(vec![rule(0.5, 3.0)])], config).unwrap();
+
+        // The delimiter is centered on the axis, and reaches as far below
+        // it as the top of the tall but shallow contents is above it.
+        let left = &delimited.contents[0];
+        let axis = config.ctx.constants.axis_height.scaled(config);
+        let extent = Length::new(3.0, Em).scaled(config) - axis;
+        assert_close((left.height + left.depth) / Px * 0.5, axis / Px);
+        assert!((left.height - left.depth) * 0.5 >= extent * 0.9);
+    }
+
+    #[test]
+    fn array_stretch_scales_row_pitch() {
+        let array = ParseNode::Array(Array {
+            rows: vec![vec![vec![ord('a')]], vec![vec![ord('c')]]],
+            ..Array::default()
+        });
+        let pitch = |config: LayoutSettings<'static, 'static>| {
+            let layout = layout(&[array.clone()], config).unwrap();
+            let ys: Vec<f64> = layout.glyphs().map(|(pos, _)| pos.y).collect();
+            ys[1] - ys[0]
+        };
+
+        let config = settings(Style::Text);
+        assert_close(pitch(config.with_array_stretch(2.0)), pitch(config) * 2.0);
+    }
+
+    #[test]
+    fn substack_alignment() {
+        let stack = |alignment| ParseNode::Stack(Stack {
+            atom_type: AtomType::Alpha,
+            lines: vec![vec![ord('a'), ord('b'), ord('c')], vec![ord('d')]],
+            line_gaps: vec![],
+            alignment,
+        });
+        // The offset of the narrow line from the start of the wide one.
+        let offset = |alignment| {
+            let layout = layout(&[stack(alignment)], settings(Style::Text)).unwrap();
+            let xs: Vec<f64> = layout.glyphs().map(|(pos, _)| pos.x).collect();
+            xs[3] - xs[0]
+        };
+
+        assert_close(offset(ArrayColumnAlign::Left), 0.0);
+        assert!(offset(ArrayColumnAlign::Centered) > 0.0);
+        assert!(offset(ArrayColumnAlign::Right) > offset(ArrayColumnAlign::Centered));
+    }
+
+    #[test]
+    fn empty_inputs() {
+        let config = settings(Style::Display);
+
+        let scripts = ParseNode::Scripts(Scripts { base: None, superscript: Some(vec![]), subscript: None });
+        let empty = layout(&[scripts], config).unwrap();
+        assert!(empty.contents.is_empty());
+        assert!(empty.width.is_zero());
+
+        // An empty radicand still gets a surd and a bar above it.
+        let radical = ParseNode::Radical(Radical { inner: vec![] });
+        let surd = layout(&[radical], config).unwrap();
+        assert_eq!(surd.glyphs().count(), 1);
+        assert!(surd.width > Length::zero());
+        assert!(extent(&surd) > 0.0);
+
+        // Empty delimiters are sized around the axis.
+        let delimited = layout(&[parens(vec![])], config).unwrap();
+        assert_eq!(delimited.glyphs().count(), 2);
+        assert!(delimited.height > config.ctx.constants.axis_height.scaled(config));
+    }
+
+    #[test]
+    fn arrow_accent_spans_base() {
+        let config = settings(Style::Text);
+        // The horizontal extent of the glyphs making up the arrow.
+        let span = |base_width| {
+            let layout = layout(&[accent("overleftrightarrow", vec![rule(base_width, 0.5)])], config).unwrap();
+            let (mut left, mut right) = (f64::INFINITY, f64::NEG_INFINITY);
+            for (pos, glyph) in layout.glyphs() {
+                let advance = config.ctx.glyph_from_gid(glyph.gid).unwrap().advance;
+                left = left.min(pos.x);
+                right = right.max(pos.x + (advance / config.ctx.units_per_em) / Em * (glyph.size / Px));
+            }
+            right - left
+        };
+
+        for &width in &[3.0, 6.0] {
+            assert!(span(width) >= 0.99 * width * 10.0, "{} does not span {}em", span(width), width);
+        }
+    }
+
+    #[test]
+    fn scale_composes_with_style() {
+        let config = settings(Style::Display);
+        let squared = || ParseNode::Scripts(Scripts {
+            base: Some(Box::new(ord('x'))),
+            superscript: Some(vec![ord('2')]),
+            subscript: None,
+        });
+        let sizes = |nodes: &[ParseNode]| -> Vec<f64> {
+            layout(nodes, config).unwrap().glyphs().map(|(_, glyph)| glyph.size / Px).collect()
+        };
+
+        let plain = sizes(&[squared()]);
+        let halved = sizes(&[ParseNode::Scale(SizeChange { factor: 0.5, inner: vec![squared()] })]);
+        assert_eq!(plain.len(), 2);
+        assert!(plain[1] < plain[0]);
+        for (&a, &b) in plain.iter().zip(&halved) {
+            assert_close(b, 0.5 * a);
+        }
+    }
+
+    #[test]
+    fn empty_delimiters_add_no_space() {
+        let config = settings(Style::Text);
+        let null = Symbol { codepoint: '.', atom_type: AtomType::Open };
+        let delimited = ParseNode::Delimited(Delimited {
+            left: null,
+            right: Symbol { atom_type: AtomType::Close, ..null },
+            inner: vec![ord('x')],
+        });
+
+        let bare = layout(&[ord('x')], config).unwrap();
+        let delimited = layout(&[delimited], config).unwrap();
+        assert_eq!(delimited.width, bare.width);
+        assert_eq!(gids(&delimited), gids(&bare));
+    }
+
+    #[test]
+    fn vcenter_centers_on_axis() {
+        let config = settings(Style::Display);
+        let axis = config.ctx.constants.axis_height.scaled(config);
+        for &height in &[0.2, 3.0] {
+            let centered = layout(&[ParseNode::VCenter(vec![rule(1.0, height)])], config).unwrap();
+            assert_close(extent(&centered), height * 10.0);
+            assert_close(((centered.height + centered.depth) * 0.5) / Px, axis / Px);
+        }
+    }
+
+    #[test]
+    fn stretched_equals_grows_with_label() {
+        let config = settings(Style::Text);
+        let equals = |label_width| ParseNode::Extensible(Extensible {
+            symbol: Symbol { codepoint: '=', atom_type: AtomType::Relation },
+            over: vec![rule(label_width, 0.5)],
+            under: vec![],
+        });
+        assert_eq!(equals(1.0).atom_type(), AtomType::Relation);
+
+        let short = layout(&[equals(1.0)], config).unwrap();
+        let long = layout(&[equals(4.0)], config).unwrap();
+        assert!(short.width / Px > 10.0);
+        assert!(long.width / Px > 40.0);
+        assert!(long.width > short.width);
+    }
+
+    #[test]
+    fn nolimits_mode_moves_scripts() {
+        let int = |superscript, subscript| ParseNode::Scripts(Scripts {
+            base: Some(Box::new(named("int"))),
+            superscript,
+            subscript,
+        });
+        // The x position of the script after the integral sign.
+        let script_x = |node: &ParseNode, mode| {
+            let config = settings(Style::Text).with_nolimits_mode(mode);
+            layout(&[node.clone()], config).unwrap().glyphs().nth(1).unwrap().0.x
+        };
+
+        let sub = int(None, Some(vec![ord('a')]));
+        assert!(script_x(&sub, 0) < script_x(&sub, 2));
+        assert!(script_x(&sub, 2) < script_x(&sub, 1));
+
+        let sup = int(Some(vec![ord('b')]), None);
+        assert_close(script_x(&sup, 0), script_x(&sup, 1));
+        assert!(script_x(&sup, 2) > script_x(&sup, 0));
+    }
+
+    #[test]
+    fn raisebox_shifts_baseline() {
+        let config = settings(Style::Text);
+        let raised = |amount| {
+            let node = ParseNode::RaiseBox(RaiseBox { amount: Unit::Px(amount), inner: vec![ord('g')] });
+            layout(&[node], config).unwrap()
+        };
+
+        let g = layout(&[ord('g')], config).unwrap();
+        for &amount in &[3.0, -3.0] {
+            let shifted = raised(amount);
+            assert_eq!(shifted.width, g.width);
+            assert_close((shifted.height - g.height) / Px, amount);
+            assert_close((shifted.depth - g.depth) / Px, amount);
+        }
+    }
+
+    #[test]
+    fn cramped_superscripts() {
+        // Distinct shifts which are larger than any other constraint.
+        let mut ctx = ctx();
+        ctx.constants.superscript_shift_up = Length::new(0.5, Em);
+        ctx.constants.superscript_shift_up_cramped = Length::new(1.0, Em);
+        let config = LayoutSettings::new(Box::leak(Box::new(ctx)), 10.0, Style::Display);
+
+        let squared = || vec![ParseNode::Scripts(Scripts {
+            base: Some(Box::new(ord('x'))),
+            superscript: Some(vec![ord('2')]),
+            subscript: None,
+        })];
+        // The shift of the superscript, which is the last glyph, above its base.
+        let shift = |nodes: &[ParseNode]| {
+            let layout = layout(nodes, config).unwrap();
+            let ys: Vec<f64> = layout.glyphs().map(|(pos, _)| pos.y).collect();
+            ys[ys.len() - 2] - ys[ys.len() - 1]
+        };
+
+        assert_close(shift(&squared()), 5.0);
+        assert_close(shift(&[frac(squared(), vec![])]), 5.0);
+        assert_close(shift(&[frac(vec![ord('a')], squared())]), 10.0);
+        assert_close(shift(&[ParseNode::Radical(Radical { inner: squared() })]), 10.0);
+    }
+
+    #[test]
+    fn fixed_delimiter_sizes() {
+        let config = settings(Style::Display);
+        let paren = |size| ParseNode::FixedDelimiter(FixedDelimiter {
+            symbol: Symbol { codepoint: '(', atom_type: AtomType::Open },
+            size,
+        });
+
+        // `\big` to `\Bigg` each grow.
+        let extents: Vec<f64> = (0..5).map(|size| extent(&layout(&[paren(size)], config).unwrap())).collect();
+        for pair in extents.windows(2) {
+            assert!(pair[1] > pair[0], "{:?}", extents);
+        }
+
+        // Size 1 is a single glyph, other than the one for `(`.
+        let big = gids(&layout(&[paren(1)], config).unwrap());
+        assert_eq!(big.len(), 1);
+        assert_ne!(big[0], config.ctx.glyph('(').unwrap().gid);
+
+        // The size does not depend on what follows.
+        let tall = frac(vec![frac(vec![ord('a')], vec![ord('b')])], vec![ord('c')]);
+        for size in 1..5 {
+            let short = layout(&[paren(size), ord('x')], config).unwrap();
+            let long = layout(&[paren(size), tall.clone()], config).unwrap();
+            assert_eq!(gids(&short)[0], gids(&long)[0]);
+        }
+    }
+
+    #[test]
+    fn scripts_attach_to_closing_delimiter() {
+        let config = settings(Style::Text);
+        let constants = &config.ctx.constants;
+        // Content taller than the delimiters grow to cover.
+        let group = || parens(vec![rule(0.5, 3.0)]);
+
+        let plain = layout(&[group()], config).unwrap();
+        let right = plain.contents.last().unwrap();
+        assert!(right.height < plain.height);
+
+        let squared = ParseNode::Scripts(Scripts {
+            base: Some(Box::new(group())),
+            superscript: Some(vec![ord('2')]),
+            subscript: None,
+        });
+        let squared = layout(&[squared], config).unwrap();
+        let (pos, _) = squared.glyphs().last().unwrap();
+        let shift = max!(
+            constants.superscript_shift_up.scaled(config),
+            right.height - constants.superscript_baseline_drop_max.scaled(config),
+            constants.superscript_bottom_min.scaled(config)
+        );
+        assert_close(-pos.y, shift / Px);
+    }
+
+    #[test]
+    fn cases_layout() {
+        let config = settings(Style::Text);
+        let rows = || vec![
+            vec![vec![ord('a')], vec![ord('b')]],
+            vec![vec![ord('a'), ord('b'), ord('c')], vec![ord('d')]],
+        ];
+
+        let cases = layout(&[ParseNode::Array(Array::cases(rows()))], config).unwrap();
+        let xs: Vec<f64> = cases.glyphs().map(|(pos, _)| pos.x).collect();
+        // A brace, followed by the cells row by row.
+        assert_eq!(xs.len(), 7);
+        assert!(xs[0] < xs[1]);
+        // Both columns are left aligned.
+        assert_close(xs[1], xs[3]);
+        assert_close(xs[2], xs[6]);
+
+        // No null delimiter space is added on the right.
+        let padded = Array { omit_right_space: false, ..Array::cases(rows()) };
+        let padded = layout(&[ParseNode::Array(padded)], config).unwrap();
+        let null_space = config.ctx.constants.null_delimiter_space.scaled(config);
+        assert_close((padded.width - cases.width) / Px, null_space / Px);
+    }
+
+    #[test]
+    fn display_sum_is_taller() {
+        // The style of the settings is replaced by the entry point.
+        let config = settings(Style::Script);
+        let inline = layout_inline(&[named("sum")], config).unwrap();
+        let display = layout_display(&[named("sum")], config).unwrap();
+        assert!(extent(&display) > extent(&inline));
+
+        let text = layout(&[named("sum")], settings(Style::Text)).unwrap();
+        assert_eq!(gids(&inline), gids(&text));
+    }
+
+    #[test]
+    fn constructed_accent_centering() {
+        let config = settings(Style::Text);
+        let width = 8.0;
+        let base = rule(width, 0.5);
+        let variant = config.ctx.horz_variant('\u{303}', config.to_font(Length::new(width, Em).scaled(config))).unwrap();
+        let parts = match variant {
+            VariantGlyph::Constructable(_, parts) => parts,
+            // Nothing to check if the font has no construction for a tilde.
+            _ => return,
+        };
+
+        let tilde = layout(&[accent("~", vec![base])], config).unwrap();
+        let to_px = |length: Length<Font>| (length / config.ctx.units_per_em) / Em * 10.0;
+        let glyphs: Vec<_> = tilde.glyphs()
+            .map(|(pos, glyph)| (pos.x, config.ctx.glyph_from_gid(glyph.gid).unwrap()))
+            .collect();
+        assert_eq!(glyphs.len(), parts.len());
+
+        // The attachment of the first part which has one, or else the middle
+        // of the middle part, lies over the center of the base.
+        let center = glyphs.iter()
+            .find(|(_, glyph)| !glyph.attachment.is_zero())
+            .map(|(x, glyph)| x + to_px(glyph.attachment))
+            .or_else(|| match glyphs.len() % 2 {
+                1 => glyphs.get(glyphs.len() / 2).map(|(x, glyph)| x + to_px(glyph.advance) * 0.5),
+                _ => None,
+            });
+        if let Some(center) = center {
+            assert!((center - width * 5.0).abs() < 1e-3, "{} is not centered", center);
+        }
+    }
+
+    #[test]
+    fn overset_relation_spacing() {
+        let config = settings(Style::Text);
+        let overset = ParseNode::OverUnder(OverUnder {
+            base: vec![sym('=', AtomType::Relation)],
+            over: vec![ord('!')],
+            under: vec![],
+            atom_type: None,
+        });
+        assert_eq!(overset.atom_type(), AtomType::Relation);
+
+        let width = |nodes: &[ParseNode]| layout(nodes, config).unwrap().width;
+        let thick = Spacing::Thick.to_length().scaled(config);
+        assert_close(
+            width(&[ord('a'), overset.clone(), ord('b')]) / Px,
+            (width(&[ord('a')]) + width(&[overset]) + width(&[ord('b')]) + thick * 2.0) / Px
+        );
+    }
+
+    #[test]
+    fn atop_has_no_rule() {
+        let config = settings(Style::Text);
+        let atop = ParseNode::GenFraction(GenFraction {
+            bar_thickness: BarThickness::None,
+            ..match frac(vec![ord('a')], vec![ord('b')]) {
+                ParseNode::GenFraction(f) => f,
+                _ => unreachable!(),
+            }
+        });
+        let rules = |node: &ParseNode| {
+            let mut out = CountingBackend::new();
+            Renderer::new().render(&layout(&[node.clone()], config).unwrap(), &mut out);
+            out.rules
+        };
+        assert_eq!(rules(&frac(vec![ord('a')], vec![ord('b')])), 1);
+        assert_eq!(rules(&atop), 0);
+
+        // The numerator and denominator keep the shifts of a fraction.
+        let ys: Vec<f64> = layout(&[atop], config).unwrap().glyphs().map(|(pos, _)| pos.y).collect();
+        let constants = &config.ctx.constants;
+        let shifts = constants.fraction_numerator_shift_up + constants.fraction_denominator_shift_down;
+        assert!(ys[1] - ys[0] >= shifts.scaled(config) / Px - 1e-6);
+    }
+
+    #[test]
+    fn prefer_fixed_delimiters() {
+        let config = settings(Style::Display);
+        let glyphs = |height: f64, config: LayoutSettings<'static, 'static>| layout(&[parens(vec![rule(0.5, height)])], config).unwrap().glyphs().count();
+
+        // The smallest content around which the parentheses are assembled.
+        let height = (0..500)
+            .map(|n| 0.5 + n as f64 * 0.01)
+            .find(|&height| glyphs(height, config) > 2)
+            .expect("no assembly for parentheses");
+        // Just beyond the largest single glyph, which is preferred instead.
+        assert_eq!(glyphs(height, config.with_prefer_fixed_delimiters(true)), 2);
+    }
+
+    #[test]
+    fn dot_is_a_null_delimiter() {
+        let config = settings(Style::Display);
+        let null = Symbol { codepoint: '.', atom_type: AtomType::Open };
+        let half_open = ParseNode::Delimited(Delimited {
+            left: null,
+            right: Symbol { codepoint: ')', atom_type: AtomType::Close },
+            inner: vec![frac(vec![ord('a')], vec![ord('b')])],
+        });
+        // Only the closing parenthesis is drawn besides `a` and `b`.
+        assert_eq!(layout(&[half_open], config).unwrap().glyphs().count(), 3);
+
+        let genfrac = ParseNode::GenFraction(GenFraction {
+            left_delimiter: Some(null),
+            right_delimiter: Some(Symbol { codepoint: '.', atom_type: AtomType::Close }),
+            ..match frac(vec![ord('a')], vec![ord('b')]) {
+                ParseNode::GenFraction(f) => f,
+                _ => unreachable!(),
+            }
+        });
+        assert_eq!(layout(&[genfrac], config).unwrap().glyphs().count(), 2);
+
+        let big = ParseNode::FixedDelimiter(FixedDelimiter { symbol: null, size: 2 });
+        assert_eq!(layout(&[big], config).unwrap().glyphs().count(), 0);
+    }
+
+    #[test]
+    fn invisible_middle() {
+        let config = settings(Style::Text);
+        let null = Symbol { codepoint: '.', atom_type: AtomType::Relation };
+        let split = layout(&[parens(vec![ord('a'), ParseNode::Middle(null), ord('b')])], config).unwrap();
+        let whole = layout(&[parens(vec![ord('a'), ord('b')])], config).unwrap();
+
+        let null_space = config.ctx.constants.null_delimiter_space.scaled(config);
+        assert_close(split.width / Px, (whole.width + null_space) / Px);
+        assert_eq!(gids(&split), gids(&whole));
+    }
+
+    #[test]
+    fn fraction_depth_includes_descender() {
+        let config = settings(Style::Display);
+        let fraction = layout(&[frac(vec![ord('1')], vec![ord('g')])], config).unwrap();
+        let (pos, g) = fraction.glyphs().last().unwrap();
+        let descender = config.ctx.glyph_from_gid(g.gid).unwrap().depth().scaled(config.denominator());
+        assert!(descender < Length::zero());
+
+        // The baseline of the denominator lies `pos.y` below that of the fraction.
+        assert_close(fraction.depth / Px, descender / Px - pos.y);
+    }
+
+    #[test]
+    fn force_large_operators() {
+        let text = settings(Style::Text);
+        let sum = |config: LayoutSettings<'static, 'static>| gids(&layout(&[named("sum")], config).unwrap());
+        let large = sum(text.with_force_large_operators(true));
+        assert_ne!(large, sum(text));
+        // The same variant as in display style, sized by `display_operator_min_height`.
+        assert_eq!(large, sum(settings(Style::Display)));
+    }
+
+    #[test]
+    fn layout_and_size_errors() {
+        let config = settings(Style::Text);
+        let (layout, size) = layout_and_size(&[ord('x')], config).unwrap();
+        assert_eq!(size, Renderer::new().size(&layout));
+        assert!(size.2 > 0.0);
+
+        let missing = '\u{10FFFD}';
+        match layout_and_size(&[ord(missing)], config) {
+            Err(err) => assert_eq!(err, Error::Layout(LayoutError::Font(FontError::MissingGlyphCodepoint(missing)))),
+            Ok(_) => panic!("laid out a missing glyph"),
+        }
+    }
+
+    #[test]
+    fn brace_labels_match_limits() {
+        let config = settings(Style::Display);
+        // The gap between the baseline of the label, which is drawn first,
+        // and the top of the base.
+        let gap = |base: ParseNode, labelled: ParseNode| {
+            let height = layout(&[base], config).unwrap().height;
+            let (pos, _) = layout(&[labelled], config).unwrap().glyphs().next().unwrap();
+            -(height / Px) - pos.y
+        };
+
+        let brace = ParseNode::Extend('\u{23DE}', Unit::Em(3.0));
+        let braced = ParseNode::OverUnder(OverUnder {
+            base: vec![brace.clone()],
+            over: vec![ord('n')],
+            under: vec![],
+            atom_type: None,
+        });
+        let sum = ParseNode::Scripts(Scripts {
+            base: Some(Box::new(named("sum"))),
+            superscript: Some(vec![ord('n')]),
+            subscript: None,
+        });
+
+        let brace_gap = gap(brace, braced);
+        assert!(brace_gap > 0.0);
+        assert_close(brace_gap, gap(named("sum"), sum));
+    }
+
+    #[test]
+    fn accent_has_width_of_base() {
+        let config = settings(Style::Text);
+        let abc = || vec![ord('a'), ord('b'), ord('c')];
+        let base = layout(&abc(), config).unwrap();
+        // As `\widehat`, which stretches the same accent.
+        let hat = layout(&[accent("^", abc())], config).unwrap();
+        assert_close(hat.width / Px, base.width / Px);
+
+        // Also when the accent is wider than its base.
+        let i = layout(&[ord('i')], config).unwrap();
+        let arrow = layout(&[accent("overrightarrow", vec![ord('i')])], config).unwrap();
+        assert_close(arrow.width / Px, i.width / Px);
+    }
+
+    #[test]
+    fn mathstrut_has_paren_extent() {
+        let config = settings(Style::Text);
+        let paren = config.ctx.glyph('(').unwrap();
+        let (height, depth) = (paren.height().scaled(config), paren.depth().scaled(config));
+
+        let strut = layout(&[ParseNode::MathStrut], config).unwrap();
+        assert!(strut.width.is_zero());
+        assert_eq!((strut.height, strut.depth), (height, depth));
+        assert_eq!(strut.glyphs().count(), 0);
+
+        // A kern would add to the height of a vertical box instead.
+        let mut vbox = builders::VBox::new();
+        vbox.add_node(strut.as_node());
+        let vbox = vbox.build();
+        assert_eq!((vbox.height, vbox.depth), (height, depth));
+    }
+
+    #[test]
+    fn underbrace_label_gap() {
+        let config = settings(Style::Display);
+        let gap = config.ctx.constants.lower_limit_gap_min.scaled(config) / Px;
+        let label = layout(&[ord('n')], config.subscript_variant()).unwrap();
+
+        // The gap is the same however deep the assembled brace is.
+        for &length in &[1.0, 3.0, 8.0] {
+            let brace = ParseNode::Extend('\u{23DF}', Unit::Em(length));
+            let bottom = -(layout(&[brace.clone()], config).unwrap().depth / Px);
+            let braced = ParseNode::OverUnder(OverUnder {
+                base: vec![brace],
+                over: vec![],
+                under: vec![ord('n')],
+                atom_type: None,
+            });
+            let (pos, _) = layout(&[braced], config).unwrap().glyphs().last().unwrap();
+            assert_close(pos.y - label.height / Px - bottom, gap);
+        }
+    }
+
+    #[test]
+    fn text_operator_limits() {
+        let lim = || ParseNode::Scripts(Scripts {
+            base: Some(Box::new(ParseNode::text_operator("lim", true))),
+            superscript: None,
+            subscript: Some(vec![ord('x')]),
+        });
+        let subscript = |style| {
+            let config = settings(style);
+            let name = layout(&[ParseNode::text_operator("lim", true)], config).unwrap();
+            let (pos, _) = layout(&[lim()], config).unwrap().glyphs().last().unwrap();
+            (pos, name.width / Px, name.depth / Px)
+        };
+
+        // Below the name in display style.
+        let (pos, width, depth) = subscript(Style::Display);
+        assert!(pos.x < width);
+        assert!(pos.y > -depth);
+
+        // To its right in text style.
+        let (pos, width, _) = subscript(Style::Text);
+        assert!(pos.x >= width);
+
+        // Unless given `\limits`, as `\lim\limits_x`.
+        let mut limits = lim();
+        limits.set_atom_type(AtomType::Operator(true));
+        let config = settings(Style::Text);
+        let width = layout(&[ParseNode::text_operator("lim", true)], config).unwrap().width / Px;
+        let (pos, _) = layout(&[limits], config).unwrap().glyphs().last().unwrap();
+        assert!(pos.x < width);
+    }
+
+    #[test]
+    fn fragment_boundary_spacing() {
+        let config = settings(Style::Text);
+        let x = layout(&[ord('x')], config).unwrap();
+        let after_operator = layout_fragment(&[ord('x')], config, AtomType::Operator(false), AtomType::Transparent).unwrap();
+        let alone = layout_fragment(&[ord('x')], config, AtomType::Transparent, AtomType::Transparent).unwrap();
+
+        // A thin space follows the operator; the space before `next` is
+        // left to the following fragment.
+        assert_close(after_operator.width / Px, x.width / Px + 10.0 / 6.0);
+        assert_eq!(alone.width, x.width);
+        let before_operator = layout_fragment(&[ord('x')], config, AtomType::Transparent, AtomType::Operator(false)).unwrap();
+        assert_eq!(before_operator.width, x.width);
+    }
+
+    #[test]
+    fn group_superscript_italic_correction() {
+        let config = settings(Style::Text);
+        let squared = |base: Vec<ParseNode>| {
+            let scripts = ParseNode::Scripts(Scripts {
+                base: Some(Box::new(ParseNode::Group(base))),
+                superscript: Some(vec![ord('2')]),
+                subscript: None,
+            });
+            let (pos, _) = layout(&[scripts], config).unwrap().glyphs().last().unwrap();
+            pos.x
+        };
+
+        let f = layout(&[ord(ITALIC_F)], config).unwrap().is_symbol().unwrap();
+        assert!(f.italics > Length::zero());
+        let xf = layout(&[ord('x'), ord(ITALIC_F)], config).unwrap();
+        assert_close(squared(vec![ord('x'), ord(ITALIC_F)]), (xf.width + f.italics) / Px);
+    }
+
+    #[test]
+    fn not_centers_slash_on_stretched_arrow() {
+        let config = settings(Style::Text);
+        let slash = layout(&[ord('/')], config).unwrap();
+        for &length in &[2.0, 6.0] {
+            let arrow = ParseNode::Extend('\u{2192}', Unit::Em(length));
+            let width = layout(&[arrow.clone()], config).unwrap().width / Px;
+            let not = layout(&[ParseNode::Not(vec![arrow])], config).unwrap();
+
+            // The slash is drawn last, and does not widen the arrow.
+            let (pos, _) = not.glyphs().last().unwrap();
+            assert_close(pos.x + slash.width / Px * 0.5, width * 0.5);
+            assert_close(not.width / Px, width);
+        }
+    }
+
+    #[test]
+    fn mid_is_spaced_as_relation() {
+        let config = settings(Style::Text);
+        let width = |nodes: &[ParseNode]| layout(nodes, config).unwrap().width / Px;
+        // The widths of `x`, the bar and `y` laid out separately.
+        let natural = |bar: &ParseNode| width(&[ord('x')]) + width(&[bar.clone()]) + width(&[ord('y')]);
+
+        // A thick space on either side of `\mid`, as in `\{x \mid y\}`.
+        let mid = named("mid");
+        let thick = 10.0 / 3.0;
+        assert_close(width(&[ord('x'), mid.clone(), ord('y')]), natural(&mid) + 2.0 * thick);
+        let set = [sym('{', AtomType::Open), ord('x'), mid, ord('y'), sym('}', AtomType::Close)];
+        assert!(width(&set) > width(&[sym('{', AtomType::Open)]) + natural(&named("mid")) + 2.0 * thick);
+
+        // A bare fence is spaced as an ordinary symbol.
+        let bar = named("|");
+        assert_close(width(&[ord('x'), bar.clone(), ord('y')]), natural(&bar));
+    }
+
+    #[test]
+    fn rlap_overlaps_next() {
+        let config = settings(Style::Text);
+        let rlap = ParseNode::Lap(Lap { side: LapSide::Right, inner: vec![ord('X')] });
+        let y = layout(&[ord('Y')], config).unwrap();
+        let lapped = layout(&[rlap, ord('Y')], config).unwrap();
+
+        assert_eq!(lapped.width, y.width);
+        let starts: Vec<f64> = lapped.glyphs().map(|(pos, _)| pos.x).collect();
+        assert_eq!(starts.len(), 2);
+        assert_close(starts[0], 0.0);
+        assert_close(starts[1], 0.0);
+    }
+
+    #[test]
+    fn glyph_mapper_substitutes() {
+        let config = settings(Style::Text);
+        let mapped = config.with_glyph_mapper(|cp, _| match cp {
+            'x' => Some('y'),
+            _ => None,
+        });
+
+        let y = gids(&layout(&[ord('y')], config).unwrap());
+        assert_eq!(gids(&layout(&[ord('x')], mapped).unwrap()), y);
+        // Other symbols are left as they are.
+        let z = gids(&layout(&[ord('z')], config).unwrap());
+        assert_eq!(gids(&layout(&[ord('z')], mapped).unwrap()), z);
+    }
+
+    #[test]
+    fn binary_without_reclassification() {
+        let config = settings(Style::Text);
+        let minus = || sym('-', AtomType::Binary);
+        let natural = (layout(&[minus()], config).unwrap().width + layout(&[ord('x')], config).unwrap().width) / Px;
+
+        // A leading `-` is a sign by default, without any space.
+        let sign = layout(&[minus(), ord('x')], config).unwrap();
+        assert_close(sign.width / Px, natural);
+
+        // Otherwise it is followed by a medium space.
+        let binary = layout(&[minus(), ord('x')], config.with_reclassify_binary(false)).unwrap();
+        assert_close(binary.width / Px, natural + 20.0 / 9.0);
+    }
+
+    #[test]
+    fn double_scripts_are_errors() {
+        let config = settings(Style::Text);
+        let scripts = |base, superscript: Option<char>, subscript: Option<char>| ParseNode::Scripts(Scripts {
+            base: Some(Box::new(base)),
+            superscript: superscript.map(|cp| vec![ord(cp)]),
+            subscript: subscript.map(|cp| vec![ord(cp)]),
+        });
+        let error = |node| layout(&[node], config).err();
+
+        // `x^y^z` and `x_y_z`.
+        let x_y = scripts(ord('x'), Some('y'), None);
+        assert_eq!(error(scripts(x_y.clone(), Some('z'), None)), Some(LayoutError::ExcessiveSuperscripts));
+        let x_y_sub = scripts(ord('x'), None, Some('y'));
+        assert_eq!(error(scripts(x_y_sub.clone(), None, Some('z'))), Some(LayoutError::ExcessiveSubscripts));
+
+        // `x^y_z` as nested scripts, and the braced `{x^y}^z`, are fine.
+        assert_eq!(error(scripts(x_y.clone(), None, Some('z'))), None);
+        assert_eq!(error(scripts(ParseNode::Group(vec![x_y]), Some('z'), None)), None);
+    }
+
+    #[test]
+    fn scale_transform_doubles_box() {
+        let config = settings(Style::Text);
+        let g = layout(&[ord('g')], config).unwrap();
+        let scaled = layout(&[ParseNode::Transform(AffineTransform::scale(2.0, 2.0), vec![ord('g')])], config).unwrap();
+
+        assert_close(scaled.width / Px, 2.0 * g.width / Px);
+        assert_close(scaled.height / Px, 2.0 * g.height / Px);
+        assert_close(scaled.depth / Px, 2.0 * g.depth / Px);
+        assert!(scaled.depth <= Length::zero());
+
+        // The glyph is drawn at the origin of the transformed box.
+        let (pos, _) = scaled.glyphs().next().unwrap();
+        assert_close(pos.x, 0.0);
+        assert_close(pos.y, 0.0);
+    }
+}
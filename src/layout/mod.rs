@@ -25,9 +25,11 @@ use crate::ast::color::RGBA;
 use crate::font::{FontContext, MathFont};
 use std::ops::Deref;
 use std::fmt;
+use std::ptr;
 use std::cmp::{max, min};
 use std::collections::BTreeMap;
 use crate::dimensions::*;
+use crate::error::LayoutError;
 
 // By default this will act as a horizontal box
 #[derive(Clone, Debug, Default)]
@@ -38,6 +40,10 @@ pub struct Layout<'f> {
     pub depth: Length<Px>,
     pub offset: Length<Px>,
     pub alignment: Alignment,
+
+    /// The height of the math axis above the baseline.  This is only set on
+    /// the layout returned from `engine::layout`.
+    pub axis_height: Length<Px>,
 }
 
 impl<'f> Layout<'f> {
@@ -69,7 +75,26 @@ impl<'f> Layout<'f> {
         self.offset = offset;
     }
 
+    /// Merge runs of adjacent horizontal kerns into single kerns.  This
+    /// preserves all metrics, and is applied to every layout built by the
+    /// engine, so nested boxes are merged as they are built.
+    pub fn coalesce_kerns(&mut self) {
+        let is_horz_kern = |node: &LayoutNode| match node.node {
+            LayoutVariant::Kern => node.height.is_zero() && node.depth.is_zero(),
+            _ => false,
+        };
+        let mut contents: Vec<LayoutNode<'f>> = Vec::with_capacity(self.contents.len());
+        for node in self.contents.drain(..) {
+            match contents.last_mut() {
+                Some(last) if is_horz_kern(last) && is_horz_kern(&node) => last.width += node.width,
+                _ => contents.push(node),
+            }
+        }
+        self.contents = contents;
+    }
+
     pub fn finalize(mut self) -> Layout<'f> {
+        self.coalesce_kerns();
         self.depth -= self.offset;
         self.height -= self.offset;
         self
@@ -81,12 +106,57 @@ impl<'f> Layout<'f> {
         self
     }
 
+    /// Widen the layout to `width`, aligning the contents within it.  The
+    /// width carried by `Centered` and `Right` is replaced by the current
+    /// width of the layout.  Does nothing if the layout is already wider.
+    pub fn pad_to_width(&mut self, width: Length<Px>, align: Alignment) {
+        if self.width >= width {
+            return;
+        }
+        self.alignment = match align {
+            Alignment::Centered(_) => Alignment::Centered(self.width),
+            Alignment::Right(_) => Alignment::Right(self.width),
+            align => align,
+        };
+        self.width = width;
+    }
+
+    /// Raise the contents by `by` relative to the baseline, or lower them if
+    /// it is negative.  The height and depth grow by `by`, while the width is
+    /// unchanged.
+    pub fn shift_baseline(&mut self, by: Length<Px>) {
+        let inner = LayoutNode {
+            width: self.width,
+            height: self.height,
+            depth: self.depth,
+            node: LayoutVariant::HorizontalBox(HorizontalBox {
+                contents: std::mem::replace(&mut self.contents, Vec::new()),
+                offset: Length::zero(),
+                alignment: self.alignment,
+            }),
+        };
+        let mut vbox = builders::VBox::new();
+        vbox.add_node(inner);
+        vbox.set_offset(-by);
+        self.contents.push(vbox.build());
+
+        self.height += by;
+        self.depth += by;
+        self.axis_height += by;
+        self.alignment = Alignment::Default;
+    }
+
     fn is_symbol(&self) -> Option<LayoutGlyph<'f>> {
         if self.contents.len() != 1 {
             return None;
         }
         self.contents[0].is_symbol()
     }
+
+    /// The glyph which ends this layout, if it ends with one.
+    fn last_glyph(&self) -> Option<LayoutGlyph<'f>> {
+        self.contents.last().and_then(LayoutNode::last_glyph)
+    }
 }
 
 #[derive(Clone)]
@@ -104,8 +174,29 @@ pub enum LayoutVariant<'f> {
     VerticalBox(VerticalBox<'f>),
     Glyph(LayoutGlyph<'f>),
     Color(ColorChange<'f>),
-    Rule,
+    Rule(LayoutRule),
     Kern,
+    Transform(TransformBox<'f>),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub struct LayoutRule {
+    pub style: RuleStyle,
+    /// The color of the rule.  `None` uses the surrounding color.
+    pub color: Option<RGBA>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RuleStyle {
+    Solid,
+    Dashed,
+    Dotted,
+}
+
+impl Default for RuleStyle {
+    fn default() -> RuleStyle {
+        RuleStyle::Solid
+    }
 }
 
 #[derive(Clone)]
@@ -114,13 +205,39 @@ pub struct ColorChange<'f> {
     pub inner: Vec<LayoutNode<'f>>,
 }
 
+/// A node drawn through an affine transform, in pixels with the y-axis
+/// pointing up and the origin on the baseline at the start of the node.
+/// The transform is translated so that the transformed node starts at the
+/// origin, and the metrics of the box are those of the transformed node.
+#[derive(Clone)]
+pub struct TransformBox<'f> {
+    pub transform: AffineTransform,
+    pub inner: Box<LayoutNode<'f>>,
+}
+
+/// Nodes placed in rows and columns.  Grids are meant to be filled densely,
+/// but the row and column metrics are kept sparse like the contents, so an
+/// outlying index costs nothing.  Rows and columns without any node take no
+/// space.
 #[derive(Clone)]
 pub struct Grid<'f> {
     pub contents: BTreeMap<(usize, usize), LayoutNode<'f>>,
     /// max length of each column
-    pub columns: Vec<Length<Px>>,
+    pub columns: BTreeMap<usize, Length<Px>>,
     /// (max height, max depth) of each row
-    pub rows: Vec<(Length<Px>, Length<Px>)>,
+    pub rows: BTreeMap<usize, (Length<Px>, Length<Px>)>,
+}
+
+/// Where the baseline of a built `Grid` lies.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GridBaseline {
+    /// The bottom edge of the grid, so that it sits on the baseline.
+    Bottom,
+    /// The baseline of the given row.  A row without any node is treated
+    /// as `Bottom`.
+    Row(usize),
+    /// Halfway between the top and the bottom edge.
+    Center,
 }
 
 #[derive(Clone, Default)]
@@ -140,6 +257,8 @@ pub struct VerticalBox<'f> {
 #[derive(Clone, Copy)]
 pub struct LayoutGlyph<'f> {
     pub gid: u16,
+    /// The character for `gid`, if known.  See `Glyph::codepoint`.
+    pub codepoint: Option<char>,
     pub size: Length<Px>,
     pub offset: Length<Px>,
     pub attachment: Length<Px>,
@@ -196,6 +315,15 @@ impl<'f> fmt::Debug for HorizontalBox<'f> {
     }
 }
 
+impl<'f> LayoutGlyph<'f> {
+    /// The position of the font this glyph is drawn from within `fonts`,
+    /// such as a chain of fallback fonts.  Fonts are compared by identity,
+    /// not by contents.
+    pub fn font_index(&self, fonts: &[&MathFont]) -> Option<usize> {
+        fonts.iter().position(|&font| ptr::eq(font, self.font))
+    }
+}
+
 impl<'f> fmt::Debug for LayoutGlyph<'f> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "LayoutGlyph({})", self.gid)
@@ -209,7 +337,7 @@ impl<'f> fmt::Debug for LayoutNode<'f> {
             LayoutVariant::HorizontalBox(ref hb) => write!(f, "HBox({:?})", hb.contents),
             LayoutVariant::VerticalBox(ref vb) => write!(f, "VBox({:?})", vb.contents),
             LayoutVariant::Glyph(ref gly) => write!(f, "Glyph({:?})", gly),
-            LayoutVariant::Rule => write!(f, "Rule()"),
+            LayoutVariant::Rule(rule) => write!(f, "Rule({:?})", rule),
             LayoutVariant::Kern => {
                 let kern = if self.width.is_zero() {
                     self.height
@@ -220,16 +348,23 @@ impl<'f> fmt::Debug for LayoutNode<'f> {
                 write!(f, "Kern({:.1})", kern)
             }
             LayoutVariant::Color(ref clr) => write!(f, "Color({:?}, {:?})", clr.color, clr.inner),
+            LayoutVariant::Transform(ref tb) => write!(f, "Transform({:?}, {:?})", tb.transform, tb.inner),
         }
     }
 }
 
 impl<'f> LayoutNode<'f> {
+    /// How far the node must be lowered (raised, if negative) so that it is
+    /// vertically centered on a math axis `axis_height` above the baseline.
+    pub fn axis_offset(&self, axis_height: Length<Px>) -> Length<Px> {
+        (self.height + self.depth) * 0.5 - axis_height
+    }
+
     /// Center the vertical about the axis.
     /// For now this ignores offsets if already applied,
     /// and will break if there already are offsets.
     fn centered(mut self, axis: Length<Px>) -> LayoutNode<'f> {
-        let shift = (self.height + self.depth) * 0.5 - axis;
+        let shift = self.axis_offset(axis);
 
         match self.node {
             LayoutVariant::VerticalBox(ref mut vb) => {
@@ -255,6 +390,49 @@ impl<'f> LayoutNode<'f> {
             _ => None,
         }
     }
+
+    fn last_glyph(&self) -> Option<LayoutGlyph<'f>> {
+        match self.node {
+            LayoutVariant::Glyph(gly) => Some(gly),
+            LayoutVariant::HorizontalBox(ref hb) => hb.contents.last().and_then(LayoutNode::last_glyph),
+            LayoutVariant::Color(ref clr) => clr.inner.last().and_then(LayoutNode::last_glyph),
+            _ => None,
+        }
+    }
+}
+
+/// Concatenate layouts horizontally, keeping their baselines aligned.
+pub fn hstack<'f>(layouts: Vec<Layout<'f>>) -> Layout<'f> {
+    let mut result = Layout::new();
+    for layout in layouts {
+        result.add_node(layout.as_node());
+    }
+    result
+}
+
+/// Stack layouts vertically, separated by `gap`, and each aligned within
+/// the widest according to `align` (see `Layout::pad_to_width`).  The
+/// baseline of the result is the baseline of the last layout.
+pub fn vstack<'f>(layouts: Vec<Layout<'f>>, align: Alignment, gap: Length<Px>) -> Layout<'f> {
+    let width = layouts.iter().map(|layout| layout.width).max().unwrap_or_default();
+    let length = layouts.len();
+
+    let mut vbox = builders::VBox::new();
+    for (idx, mut layout) in layouts.into_iter().enumerate() {
+        layout.pad_to_width(width, align);
+        let depth = layout.depth;
+        vbox.add_node(layout.as_node());
+
+        // Vertical boxes only account for heights, so make
+        // room for the depth of each line before the next.
+        if idx + 1 < length {
+            vbox.add_node(kern!(vert: gap - depth));
+        }
+    }
+
+    let mut result = Layout::new();
+    result.add_node(vbox.build());
+    result
 }
 
 pub fn is_symbol<'a, 'b: 'a>(contents: &'a [LayoutNode<'b>]) -> Option<LayoutGlyph<'b>> {
@@ -268,7 +446,7 @@ pub fn is_symbol<'a, 'b: 'a>(contents: &'a [LayoutNode<'b>]) -> Option<LayoutGly
 /// Display styles which are used in scaling glyphs.  The associated
 /// methods are taken from pg.441 from the TeXBook
 #[allow(dead_code)]
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Style {
     ScriptScriptCramped,
     ScriptScript,
@@ -358,6 +536,55 @@ pub struct LayoutSettings<'a, 'f> {
     pub ctx: &'a FontContext<'f>,
     pub font_size: Scale<Px, Em>,
     pub style: Style,
+
+    /// Insert the italics correction of a slanted symbol when it is
+    /// directly followed by a closing or punctuation atom, e.g. `f)`.
+    pub italic_correction: bool,
+
+    /// How the italics correction of an operator without limits, such as
+    /// `\int`, offsets its scripts, like `\mathnolimitsmode` in LuaTeX.
+    /// `0` (the default) moves the subscript left by the correction, `1`
+    /// applies no correction, and `2` moves the subscript left and the
+    /// superscript right by half of the correction each.
+    pub nolimits_mode: u8,
+
+    /// The maximum nesting depth of the layout, after which
+    /// `LayoutError::MaxDepthExceeded` is returned.
+    pub max_depth: usize,
+
+    /// Scales the strut height, strut depth and row separation of arrays,
+    /// like `\arraystretch`.
+    pub array_stretch: f64,
+
+    /// Scales the space between array columns, like `\arraycolsep`.
+    pub array_col_sep: f64,
+
+    /// Prefer a single glyph for `\left` and `\right` delimiters over an
+    /// assembled one, if it falls short of the required size by no more than
+    /// `delimiter_short_fall`.  Assemblies can show seams at small sizes.
+    pub prefer_fixed_delimiters: bool,
+
+    /// Use the display size of large operators, such as `\sum` and `\int`,
+    /// in every style rather than only in display style.
+    pub force_large_operators: bool,
+
+    /// The thinnest fraction bar that is drawn.  Thinner bars, other than
+    /// those of zero thickness, are thickened to this, so that they remain
+    /// visible.  One pixel by default.
+    pub min_bar_thickness: Length<Px>,
+
+    /// Consulted for every symbol before its glyph is looked up, with the
+    /// codepoint of the symbol and the current style.  Returning a codepoint
+    /// lays out that glyph instead; `None` keeps the symbol as it is.
+    pub glyph_mapper: Option<fn(char, Style) -> Option<char>>,
+
+    /// Treat a binary atom as an ordinary one where it has no operand on
+    /// either side, as TeX does for a leading `-`.  Enabled by default; when
+    /// disabled, binary atoms are always spaced as binary.
+    pub reclassify_binary: bool,
+
+    /// The current nesting depth.
+    depth: usize,
 }
 
 impl<'a, 'f> LayoutSettings<'a, 'f> {
@@ -366,9 +593,117 @@ impl<'a, 'f> LayoutSettings<'a, 'f> {
             ctx,
             font_size: Scale::new(font_size, Px, Em),
             style,
+            italic_correction: false,
+            nolimits_mode: 0,
+            max_depth: 256,
+            array_stretch: 1.0,
+            array_col_sep: 1.0,
+            prefer_fixed_delimiters: false,
+            force_large_operators: false,
+            min_bar_thickness: Length::new(1.0, Px),
+            glyph_mapper: None,
+            reclassify_binary: true,
+            depth: 0,
+        }
+    }
+
+    /// Create settings for a font size given in points, rendered at `dpi`.
+    /// A point is 1/72 of an inch, so the size in pixels per em is
+    /// `pt * dpi / 72`; at 96 dpi, 12pt is 16px.
+    pub fn from_point_size(ctx: &'a FontContext<'f>, pt: f64, dpi: f64, style: Style) -> Self {
+        LayoutSettings::new(ctx, pt * dpi / 72.0, style)
+    }
+
+    /// Multiply the font size by `factor`.  This composes with the
+    /// scaling applied to scripts by the math style.
+    pub fn with_size_factor(self, factor: f64) -> Self {
+        LayoutSettings {
+            font_size: Scale::new(self.font_size.factor * factor, Px, Em),
+            ..self
         }
     }
 
+    pub fn with_nolimits_mode(self, nolimits_mode: u8) -> Self {
+        LayoutSettings {
+            nolimits_mode,
+            ..self
+        }
+    }
+
+    pub fn with_max_depth(self, max_depth: usize) -> Self {
+        LayoutSettings {
+            max_depth,
+            ..self
+        }
+    }
+
+    pub fn with_array_stretch(self, array_stretch: f64) -> Self {
+        LayoutSettings {
+            array_stretch,
+            ..self
+        }
+    }
+
+    pub fn with_array_col_sep(self, array_col_sep: f64) -> Self {
+        LayoutSettings {
+            array_col_sep,
+            ..self
+        }
+    }
+
+    pub fn with_prefer_fixed_delimiters(self, enabled: bool) -> Self {
+        LayoutSettings {
+            prefer_fixed_delimiters: enabled,
+            ..self
+        }
+    }
+
+    pub fn with_force_large_operators(self, enabled: bool) -> Self {
+        LayoutSettings {
+            force_large_operators: enabled,
+            ..self
+        }
+    }
+
+    pub fn with_min_bar_thickness(self, min_bar_thickness: Length<Px>) -> Self {
+        LayoutSettings {
+            min_bar_thickness,
+            ..self
+        }
+    }
+
+    pub fn with_reclassify_binary(self, enabled: bool) -> Self {
+        LayoutSettings {
+            reclassify_binary: enabled,
+            ..self
+        }
+    }
+
+    pub fn with_glyph_mapper(self, glyph_mapper: fn(char, Style) -> Option<char>) -> Self {
+        LayoutSettings {
+            glyph_mapper: Some(glyph_mapper),
+            ..self
+        }
+    }
+
+    pub fn with_italic_correction(self, enabled: bool) -> Self {
+        LayoutSettings {
+            italic_correction: enabled,
+            ..self
+        }
+    }
+
+    /// Enter one level deeper into the layout, failing if this exceeds `max_depth`.
+    fn nested(self) -> Result<Self, LayoutError> {
+        if self.depth >= self.max_depth {
+            return Err(LayoutError::MaxDepthExceeded);
+        }
+        Ok(LayoutSettings {
+            depth: self.depth + 1,
+            ..self
+        })
+    }
+
     fn cramped(self) -> Self {
         LayoutSettings {
             style: self.style.cramped(),
@@ -417,4 +752,128 @@ impl<'a, 'f> LayoutSettings<'a, 'f> {
             ..self
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    #[test]
+    fn point_size_at_dpi() {
+        let ctx = ctx();
+        let config = LayoutSettings::from_point_size(&ctx, 12.0, 96.0, Style::Text);
+        assert_close(config.font_size.factor, 16.0);
+        assert_close(config.font_size.factor, LayoutSettings::new(&ctx, 16.0, Style::Text).font_size.factor);
+    }
+
+    #[test]
+    fn pad_to_width() {
+        let px = |value: f64| Length::new(value, Px);
+        let x = engine::layout(&[ord('x')], settings(Style::Text)).unwrap();
+        let natural = x.width;
+        assert!(natural < px(20.0));
+
+        let mut padded = x.clone();
+        padded.pad_to_width(px(20.0), Alignment::Right(Length::zero()));
+        assert_eq!(padded.width, px(20.0));
+        assert_eq!(padded.alignment, Alignment::Right(natural));
+        assert_eq!(padded.contents.len(), x.contents.len());
+        assert_eq!((padded.height, padded.depth), (x.height, x.depth));
+
+        let mut left = x.clone();
+        left.pad_to_width(px(20.0), Alignment::Left);
+        assert_eq!(left.alignment, Alignment::Left);
+
+        // Already wide enough.
+        let mut unchanged = x.clone();
+        unchanged.pad_to_width(natural * 0.5, Alignment::Centered(Length::zero()));
+        assert_eq!(unchanged.width, natural);
+        assert_eq!(unchanged.alignment, x.alignment);
+    }
+
+    #[test]
+    fn stacking_layouts() {
+        let config = settings(Style::Text);
+        let glyph = |cp| engine::layout(&[ord(cp)], config).unwrap();
+        let (x, g) = (glyph('x'), glyph('g'));
+
+        let row = hstack(vec![x.clone(), g.clone()]);
+        assert_eq!(row.width, x.width + g.width);
+        assert_eq!(row.height, max(x.height, g.height));
+        assert_eq!(row.depth, min(x.depth, g.depth));
+
+        let gap = Length::new(2.0, Px);
+        let column = vstack(vec![g.clone(), x.clone()], Alignment::Centered(Length::zero()), gap);
+        assert_eq!(column.width, max(x.width, g.width));
+        // The baseline is that of the last line.
+        assert_eq!(column.depth, x.depth);
+        assert_close(
+            (column.height - column.depth) / Px,
+            (g.height - g.depth + gap + x.height - x.depth) / Px
+        );
+    }
+
+    #[test]
+    fn shift_baseline() {
+        let g = engine::layout(&[ord('g')], settings(Style::Text)).unwrap();
+        let by = Length::new(5.0, Px);
+
+        let mut up = g.clone();
+        up.shift_baseline(by);
+        assert_eq!(up.width, g.width);
+        assert_close((up.height - g.height) / Px, 5.0);
+        assert_close((up.depth - g.depth) / Px, 5.0);
+
+        let mut down = g.clone();
+        down.shift_baseline(-by);
+        assert_close((down.height - g.height) / Px, -5.0);
+        assert_close((down.depth - g.depth) / Px, -5.0);
+    }
+
+    #[test]
+    fn axis_offset() {
+        let px = |value: f64| Length::new(value, Px);
+        let axis = px(2.5);
+        let symmetric = builders::strut(px(4.0), px(-4.0));
+        assert_eq!(symmetric.axis_offset(axis), px(-2.5));
+
+        // A box above the baseline is lowered until its middle is at the axis.
+        let asymmetric = builders::strut(px(10.0), px(-2.0));
+        assert_eq!(asymmetric.axis_offset(axis), px(1.5));
+        let mut vbox = builders::VBox::new();
+        vbox.add_node(asymmetric);
+        let centered = vbox.build().centered(axis);
+        assert_eq!(centered.height - centered.depth, px(12.0));
+        assert_eq!((centered.height + centered.depth) * 0.5, axis);
+    }
+
+    #[test]
+    fn coalesce_kerns() {
+        let px = |value: f64| Length::new(value, Px);
+        let x = engine::layout(&[ord('x')], settings(Style::Text)).unwrap();
+
+        let mut layout = Layout::new();
+        layout.add_node(kern!(horz: px(2.0)));
+        layout.add_node(kern!(horz: px(3.0)));
+        layout.add_node(x.as_node());
+        layout.add_node(kern!(horz: px(1.0)));
+        let width = layout.width;
+
+        let layout = layout.finalize();
+        assert_eq!(layout.contents.len(), 3);
+        assert_eq!(layout.contents[0].width, px(5.0));
+        assert_eq!(layout.width, width);
+    }
+
+    #[test]
+    fn font_index_in_chain() {
+        // Two copies of the same font, which are told apart by identity.
+        let (first, second) = (font(), font());
+        let ctx = Box::leak(Box::new(FontContext::new(second)));
+        let layout = engine::layout(&[ord('x')], LayoutSettings::new(ctx, 10.0, Style::Text)).unwrap();
+        let (_, glyph) = layout.glyphs().next().unwrap();
+
+        assert_eq!(glyph.font_index(&[first, second]), Some(1));
+        assert_eq!(glyph.font_index(&[first]), None);
+    }
+}
@@ -3,6 +3,15 @@ use crate::layout::Style;
 use crate::dimensions::{Length, Em};
 
 pub fn atom_space(left: AtomType, right: AtomType, style: Style) -> Spacing {
+    // A fence which is not part of a `\left ... \right` pair, such as a
+    // bare `|`, is spaced as an ordinary symbol.  Use `\mid` for a bar
+    // spaced as a relation.
+    let ordinary = |at: AtomType| match at {
+        AtomType::Fence => AtomType::Alpha,
+        at => at,
+    };
+    let (left, right) = (ordinary(left), ordinary(right));
+
     if style >= Style::TextCramped {
         match (left, right) {
             (AtomType::Alpha,       AtomType::Operator(_)) => Spacing::Thin,
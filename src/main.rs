@@ -10,6 +10,8 @@ pub mod ast;
 pub mod render;
 pub mod font;
 pub mod dev;
+#[cfg(test)]
+mod testing;
 
 fn main() {
     // dev::dev();
@@ -0,0 +1,125 @@
+use web_sys::CanvasRenderingContext2d;
+use wasm_bindgen::JsValue;
+use pathfinder_content::outline::Outline;
+use pathfinder_geometry::{
+    transform2d::Transform2F,
+    vector::Vector2F,
+    rect::RectF,
+};
+use super::{Backend, Cursor};
+use super::outline::{decompose, PathSink};
+use super::scene::transform_about;
+use crate::font::MathFont;
+use crate::ast::color::RGBA;
+use crate::dimensions::AffineTransform;
+
+fn v_xy(x: f64, y: f64) -> Vector2F {
+    Vector2F::new(x as f32, y as f32)
+}
+
+fn css_color(RGBA(r, g, b, a): RGBA) -> JsValue {
+    JsValue::from_str(&format!("rgba({},{},{},{})", r, g, b, a as f64 / 255.0))
+}
+
+/// A backend drawing directly onto a 2D canvas context, without going through
+/// the pathfinder renderer.  The canvas uses y-down coordinates, so this
+/// expects `Renderer::y_up` to be `false`.
+pub struct CanvasBackend<'a> {
+    ctx: &'a CanvasRenderingContext2d,
+    color_stack: Vec<JsValue>,
+    transform: Transform2F,
+    transform_stack: Vec<Transform2F>,
+    color: JsValue,
+}
+
+impl<'a> CanvasBackend<'a> {
+    pub fn new(ctx: &'a CanvasRenderingContext2d) -> Self {
+        CanvasBackend::with_transform(ctx, Transform2F::default())
+    }
+    pub fn with_transform(ctx: &'a CanvasRenderingContext2d, transform: Transform2F) -> Self {
+        CanvasBackend {
+            ctx,
+            color_stack: Vec::new(),
+            transform,
+            transform_stack: Vec::new(),
+            color: css_color(RGBA(0, 0, 0, 0xff)),
+        }
+    }
+
+    fn fill_outline(&self, outline: &Outline, tr: Transform2F) {
+        self.ctx.begin_path();
+        decompose(outline, tr, &mut CanvasPath(self.ctx));
+        self.ctx.set_fill_style(&self.color);
+        self.ctx.fill();
+    }
+}
+
+struct CanvasPath<'a>(&'a CanvasRenderingContext2d);
+
+impl<'a> PathSink for CanvasPath<'a> {
+    fn move_to(&mut self, p: Vector2F) {
+        self.0.move_to(p.x() as f64, p.y() as f64);
+    }
+    fn line_to(&mut self, p: Vector2F) {
+        self.0.line_to(p.x() as f64, p.y() as f64);
+    }
+    fn quad_to(&mut self, c: Vector2F, p: Vector2F) {
+        self.0.quadratic_curve_to(c.x() as f64, c.y() as f64, p.x() as f64, p.y() as f64);
+    }
+    fn cubic_to(&mut self, c0: Vector2F, c1: Vector2F, p: Vector2F) {
+        self.0.bezier_curve_to(
+            c0.x() as f64, c0.y() as f64,
+            c1.x() as f64, c1.y() as f64,
+            p.x() as f64, p.y() as f64
+        );
+    }
+    fn close(&mut self) {
+        self.0.close_path();
+    }
+}
+
+impl<'a> Backend for CanvasBackend<'a> {
+    fn symbol(&mut self, pos: Cursor, gid: u16, scale: f64, font: &MathFont) {
+        use font::{Font, GlyphId};
+        let path = font.glyph(GlyphId(gid as u32)).unwrap().path;
+        let tr = self.transform
+            * Transform2F::from_translation(v_xy(pos.x, pos.y))
+            * Transform2F::from_scale(v_xy(scale, -scale))
+            * font.font_matrix();
+
+        self.fill_outline(&path, tr);
+    }
+    fn rule(&mut self, pos: Cursor, width: f64, height: f64) {
+        // Filled as a path through `self.transform`, like the glyphs, so
+        // that both are drawn under the current transform of the context.
+        let rect = Outline::from_rect(RectF::new(v_xy(pos.x, pos.y), v_xy(width, height)));
+        self.fill_outline(&rect, self.transform);
+    }
+    fn begin_color(&mut self, color: RGBA) {
+        let prev = std::mem::replace(&mut self.color, css_color(color));
+        self.color_stack.push(prev);
+    }
+    fn end_color(&mut self) {
+        self.color = self.color_stack.pop().unwrap();
+    }
+    fn begin_transform(&mut self, origin: Cursor, transform: AffineTransform) {
+        self.transform_stack.push(self.transform);
+        self.transform = self.transform * transform_about(origin, transform);
+    }
+    fn end_transform(&mut self) {
+        self.transform = self.transform_stack.pop().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_backend<B: Backend>() {}
+
+    // Drawing needs a browser, so only check that the backend builds.
+    #[test]
+    fn canvas_is_a_backend() {
+        assert_backend::<CanvasBackend<'static>>();
+    }
+}
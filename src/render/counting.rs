@@ -0,0 +1,84 @@
+use super::{Backend, Cursor, Role};
+use crate::font::MathFont;
+use crate::layout::RuleStyle;
+use crate::ast::color::RGBA;
+
+/// A backend which draws nothing, for checking that a layout renders
+/// without panicking.
+#[derive(Default)]
+pub struct NullBackend;
+
+impl Backend for NullBackend {
+    fn symbol(&mut self, _pos: Cursor, _gid: u16, _scale: f64, _font: &MathFont) {}
+    fn rule(&mut self, _pos: Cursor, _width: f64, _height: f64) {}
+    fn begin_color(&mut self, _color: RGBA) {}
+    fn end_color(&mut self) {}
+}
+
+/// A backend which counts the calls made to it and draws nothing.
+/// Styled rules and frames are counted as rules.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct CountingBackend {
+    pub symbols: usize,
+    pub rules: usize,
+    pub bboxes: usize,
+    pub colors: usize,
+}
+
+impl CountingBackend {
+    pub fn new() -> Self {
+        CountingBackend::default()
+    }
+}
+
+impl Backend for CountingBackend {
+    fn bbox(&mut self, _pos: Cursor, _width: f64, _height: f64, _role: Role) {
+        self.bboxes += 1;
+    }
+    fn symbol(&mut self, _pos: Cursor, _gid: u16, _scale: f64, _font: &MathFont) {
+        self.symbols += 1;
+    }
+    fn rule(&mut self, _pos: Cursor, _width: f64, _height: f64) {
+        self.rules += 1;
+    }
+    fn rule_styled(&mut self, _pos: Cursor, _width: f64, _height: f64, _style: RuleStyle) {
+        self.rules += 1;
+    }
+    fn frame(&mut self, _pos: Cursor, _width: f64, _height: f64, _line_width: f64, _radius: f64) {
+        self.rules += 1;
+    }
+    fn begin_color(&mut self, _color: RGBA) {
+        self.colors += 1;
+    }
+    fn end_color(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+    use crate::ast::nodes::{BarThickness, GenFraction, MathStyle, ParseNode};
+    use crate::dimensions::Unit;
+    use crate::layout::{engine, Style};
+    use crate::render::Renderer;
+
+    #[test]
+    fn counts_a_fraction() {
+        let fraction = ParseNode::GenFraction(GenFraction {
+            numerator: vec![ord('a'), ord('b')],
+            denominator: vec![ord('c')],
+            bar_thickness: BarThickness::Default,
+            left_delimiter: None,
+            right_delimiter: None,
+            style: MathStyle::NoChange,
+            bar_padding: Unit::Em(0.0),
+        });
+        let layout = engine::layout(&[fraction], settings(Style::Display)).unwrap();
+
+        let mut out = CountingBackend::new();
+        Renderer::new().render(&layout, &mut out);
+        assert_eq!(out, CountingBackend { symbols: 3, rules: 1, bboxes: 0, colors: 0 });
+
+        Renderer::new().render(&layout, &mut NullBackend);
+    }
+}
@@ -0,0 +1,135 @@
+//! Walk the glyphs of a layout with their absolute positions, following
+//! the same traversal as the `Renderer`.
+
+use std::collections::BTreeSet;
+use super::Cursor;
+use crate::dimensions::*;
+use crate::layout::{Layout, LayoutNode, LayoutVariant, LayoutGlyph, Alignment, Grid, TransformBox};
+
+impl<'f> Layout<'f> {
+    /// All glyphs of the layout, together with the position of their origin
+    /// on the baseline.  Positions use the same y-down coordinates as the
+    /// default `Renderer`, relative to the baseline of the layout.
+    pub fn glyphs<'l>(&'l self) -> impl Iterator<Item = (Cursor, &'l LayoutGlyph<'f>)> {
+        let mut out = Vec::new();
+        hbox(&mut out, Cursor::default(), &self.contents, self.width / Px, self.alignment);
+        out.into_iter()
+    }
+
+    /// The ids of all glyphs used by the layout, for subsetting the font.
+    /// Rules are drawn without glyphs, so a fraction bar adds none.
+    pub fn used_glyphs(&self) -> BTreeSet<u16> {
+        self.glyphs().map(|(_, gly)| gly.gid).collect()
+    }
+}
+
+type Glyphs<'l, 'f> = Vec<(Cursor, &'l LayoutGlyph<'f>)>;
+
+fn hbox<'l, 'f>(out: &mut Glyphs<'l, 'f>, mut pos: Cursor, nodes: &'l [LayoutNode<'f>], width: f64, alignment: Alignment) {
+    match alignment {
+        Alignment::Centered(w) => pos.x += (width - w / Px) * 0.5,
+        Alignment::Right(w) => pos.x += width - w / Px,
+        _ => (),
+    }
+
+    for node in nodes {
+        self::node(out, pos, node);
+        pos.x += node.width / Px;
+    }
+}
+
+fn vbox<'l, 'f>(out: &mut Glyphs<'l, 'f>, mut pos: Cursor, nodes: &'l [LayoutNode<'f>]) {
+    for node in nodes {
+        match node.node {
+            LayoutVariant::Grid(ref g) => grid(out, pos, g),
+            LayoutVariant::HorizontalBox(ref hb) => {
+                hbox(out, pos.down(node.height / Px), &hb.contents, node.width / Px, hb.alignment)
+            }
+            LayoutVariant::VerticalBox(ref vb) => vbox(out, pos, &vb.contents),
+            LayoutVariant::Glyph(ref gly) => out.push((pos.down(node.height / Px), gly)),
+            LayoutVariant::Transform(ref tb) => transform(out, pos.down(node.height / Px), tb),
+            LayoutVariant::Color(_) | LayoutVariant::Rule(_) | LayoutVariant::Kern => (),
+        }
+
+        pos.y += node.height / Px;
+    }
+}
+
+fn node<'l, 'f>(out: &mut Glyphs<'l, 'f>, pos: Cursor, node: &'l LayoutNode<'f>) {
+    match node.node {
+        LayoutVariant::Glyph(ref gly) => out.push((pos, gly)),
+        LayoutVariant::VerticalBox(ref vb) => vbox(out, pos.up(node.height / Px), &vb.contents),
+        LayoutVariant::HorizontalBox(ref hb) => hbox(out, pos, &hb.contents, node.width / Px, hb.alignment),
+        LayoutVariant::Grid(ref g) => grid(out, pos.up(node.height / Px), g),
+        LayoutVariant::Color(ref clr) => hbox(out, pos, &clr.inner, node.width / Px, Alignment::Default),
+        LayoutVariant::Transform(ref tb) => transform(out, pos, tb),
+        LayoutVariant::Rule(_) | LayoutVariant::Kern => (),
+    }
+}
+
+/// Only the origins of the glyphs are transformed, not their shapes.
+fn transform<'l, 'f>(out: &mut Glyphs<'l, 'f>, pos: Cursor, tb: &'l TransformBox<'f>) {
+    let mut inner = Vec::new();
+    node(&mut inner, Cursor::default(), &tb.inner);
+    let t = tb.transform.flip_y();
+    out.extend(inner.into_iter().map(|(c, gly)| {
+        let (x, y) = t.apply(c.x, c.y);
+        (pos.translate(x, y), gly)
+    }));
+}
+
+fn grid<'l, 'f>(out: &mut Glyphs<'l, 'f>, pos: Cursor, grid: &'l Grid<'f>) {
+    let x_offsets = grid.x_offsets();
+    let y_offsets = grid.y_offsets();
+    for (&(row, column), n) in grid.contents.iter() {
+        let (height, _) = grid.rows[&row];
+        node(out, pos.translate(x_offsets[&column] / Px, (y_offsets[&row] + height) / Px), n);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+    use crate::ast::nodes::{BarThickness, GenFraction, MathStyle, ParseNode, Scripts};
+    use crate::dimensions::Unit;
+    use crate::font::AtomType;
+    use crate::layout::{Style, engine};
+    use crate::render::{Renderer, CountingBackend};
+
+    #[test]
+    fn one_glyph_per_symbol_call() {
+        let nodes = vec![
+            ParseNode::Scripts(Scripts {
+                base: Some(Box::new(named("sum"))),
+                superscript: Some(vec![ord('n')]),
+                subscript: Some(vec![ord('k'), sym('=', AtomType::Relation), ord('1')]),
+            }),
+            ord('k'),
+        ];
+        let layout = engine::layout(&nodes, settings(Style::Display)).unwrap();
+        let mut out = CountingBackend::new();
+        Renderer::new().render(&layout, &mut out);
+        assert_eq!(layout.glyphs().count(), out.symbols);
+        assert_eq!(out.symbols, 6);
+    }
+
+    #[test]
+    fn used_glyphs_of_a_fraction() {
+        let config = settings(Style::Display);
+        let gid = |cp| engine::layout(&[ord(cp)], config).unwrap().glyphs().next().unwrap().1.gid;
+        let fraction = ParseNode::GenFraction(GenFraction {
+            numerator: vec![ord('a')],
+            denominator: vec![ord('b')],
+            bar_thickness: BarThickness::Default,
+            left_delimiter: None,
+            right_delimiter: None,
+            style: MathStyle::NoChange,
+            bar_padding: Unit::Em(0.0),
+        });
+
+        // The bar is a rule, which uses no glyph.
+        let layout = engine::layout(&[fraction], config).unwrap();
+        assert_eq!(layout.used_glyphs(), vec![gid('a'), gid('b')].into_iter().collect());
+    }
+}
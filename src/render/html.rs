@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use super::{Backend, Cursor};
+use crate::font::{MathFont, reverse_cmap};
+use crate::layout::Layout;
+use crate::ast::color::RGBA;
+use crate::dimensions::{AffineTransform, Px};
+
+const FONT_FAMILY: &str = "subscript-math";
+
+/// A backend which emits a `<div>` of absolutely positioned `<span>`s, one
+/// per glyph, and `<div>`s for rules.  Glyphs are written as the codepoint
+/// the font maps to them, so the font found at `font_url` must be the same
+/// font that was used for layout.  Glyphs without a codepoint (such as the
+/// parts of a constructed delimiter) cannot be represented and are skipped.
+pub struct HtmlBackend {
+    font_url: String,
+    body: String,
+    /// The reverse cmap of each font seen so far.
+    chars: HashMap<*const MathFont, HashMap<u16, char>>,
+}
+
+impl HtmlBackend {
+    pub fn new(font_url: impl Into<String>) -> Self {
+        HtmlBackend {
+            font_url: font_url.into(),
+            body: String::new(),
+            chars: HashMap::new(),
+        }
+    }
+
+    /// Wrap the rendered spans in a self-contained `<div>` sized to `layout`.
+    pub fn into_html(self, layout: &Layout) -> String {
+        let mut out = String::new();
+        write!(out,
+            "<div style=\"position:relative;display:inline-block;vertical-align:{}px;width:{}px;height:{}px\">\
+             <style>@font-face{{font-family:'{}';src:url('{}')}}</style>",
+            layout.depth / Px,
+            layout.width / Px,
+            (layout.height - layout.depth) / Px,
+            FONT_FAMILY,
+            css_escape(&self.font_url)
+        ).unwrap();
+
+        // Cursors are relative to the baseline, so shift everything
+        // down by the height of the layout.
+        write!(out,
+            "<div style=\"position:absolute;left:0;top:{}px;font-family:'{}'\">{}</div></div>",
+            layout.height / Px,
+            FONT_FAMILY,
+            self.body
+        ).unwrap();
+        out
+    }
+
+    fn codepoint(&mut self, gid: u16, font: &MathFont) -> Option<char> {
+        self.chars
+            .entry(font as *const MathFont)
+            .or_insert_with(|| reverse_cmap(font))
+            .get(&gid)
+            .cloned()
+    }
+}
+
+/// Escape `s` for a quoted CSS string.  Quotes, backslashes, markup
+/// characters and control characters are written as CSS hex escapes, so
+/// the result is also safe within an HTML element or attribute.
+fn css_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\'' | '"' | '\\' | '<' | '>' | '&' => write!(out, "\\{:X} ", c as u32).unwrap(),
+            c if c.is_control() => write!(out, "\\{:X} ", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+impl Backend for HtmlBackend {
+    fn symbol(&mut self, pos: Cursor, gid: u16, scale: f64, font: &MathFont) {
+        let cp = match self.codepoint(gid, font) {
+            Some(cp) => cp,
+            None => return,
+        };
+        // `line-height: 0` collapses the line box so the glyph sits on `top`
+        // rather than below it.  This is exact only for fonts whose ascent
+        // and descent are balanced.
+        write!(self.body,
+            "<span style=\"position:absolute;left:{}px;top:{}px;font-size:{}px;line-height:0;white-space:pre\">&#x{:X};</span>",
+            pos.x, pos.y, scale, cp as u32
+        ).unwrap();
+    }
+    fn rule(&mut self, pos: Cursor, width: f64, height: f64) {
+        write!(self.body,
+            "<div style=\"position:absolute;left:{}px;top:{}px;width:{}px;height:{}px;background:currentColor\"></div>",
+            pos.x, pos.y, width, height
+        ).unwrap();
+    }
+    fn begin_color(&mut self, RGBA(r, g, b, a): RGBA) {
+        write!(self.body,
+            "<span style=\"color:rgba({},{},{},{})\">",
+            r, g, b, a as f64 / 255.0
+        ).unwrap();
+    }
+    fn end_color(&mut self) {
+        self.body.push_str("</span>");
+    }
+    /// The contents are wrapped in an element at the same origin as the
+    /// rest, so that their positions are unchanged, with a CSS transform.
+    fn begin_transform(&mut self, origin: Cursor, t: AffineTransform) {
+        write!(self.body,
+            "<span style=\"position:absolute;left:0;top:0;transform-origin:{}px {}px;transform:matrix({},{},{},{},{},{})\">",
+            origin.x, origin.y, t.xx, t.yx, t.xy, t.yy, t.tx, t.ty
+        ).unwrap();
+    }
+    fn end_transform(&mut self) {
+        self.body.push_str("</span>");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+    use crate::font::AtomType;
+    use crate::layout::{Style, engine};
+    use crate::render::Renderer;
+
+    #[test]
+    fn one_span_per_glyph() {
+        let nodes = [ord('a'), sym('+', AtomType::Binary), ord('b')];
+        let layout = engine::layout(&nodes, settings(Style::Text)).unwrap();
+        let mut html = HtmlBackend::new("math.otf");
+        Renderer::new().render(&layout, &mut html);
+        let html = html.into_html(&layout);
+        assert_eq!(html.matches("<span").count(), 3);
+    }
+
+    #[test]
+    fn font_url_is_escaped() {
+        assert_eq!(css_escape("a'b\"c\\"), "a\\27 b\\22 c\\5C ");
+        assert_eq!(css_escape("</style>"), "\\3C /style\\3E ");
+
+        let layout = Layout::new();
+        let html = HtmlBackend::new("x');}</style><script>").into_html(&layout);
+        assert!(!html.contains("<script>"));
+        assert!(!html.contains("x')"));
+    }
+
+    #[test]
+    fn transforms_wrap_contents() {
+        let mut html = HtmlBackend::new("math.otf");
+        html.begin_transform(Cursor { x: 1.0, y: 2.0 }, AffineTransform::scale(2.0, 3.0));
+        html.rule(Cursor { x: 0.0, y: 0.0 }, 1.0, 1.0);
+        html.end_transform();
+
+        assert!(html.body.starts_with("<span style=\"position:absolute;left:0;top:0;transform-origin:1px 2px;transform:matrix(2,0,0,3,0,0)\">"));
+        assert!(html.body.ends_with("</div></span>"));
+    }
+}
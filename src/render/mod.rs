@@ -8,12 +8,41 @@ use crate::layout::{
     Style,
     LayoutSettings,
     Layout,
-    Grid
+    Grid,
+    LayoutRule,
+    RuleStyle,
+    TransformBox,
 };
 use crate::ast::color::RGBA;
+use std::ptr;
 
 pub struct Renderer {
     pub debug: bool,
+
+    /// The direction of the y-axis passed to the `Backend`.
+    ///
+    /// By default (`false`) the y-axis points down: the baseline of the
+    /// layout is at `y = 0` and content above it has negative `y`.  The
+    /// position of a rule or bounding box is its top-left corner.
+    ///
+    /// With `y_up` all `y` coordinates are negated, so content above the
+    /// baseline has positive `y`, and the position of a rule or bounding box
+    /// is its bottom-left corner.  Glyph positions are always the origin of
+    /// the glyph on its baseline.
+    pub y_up: bool,
+
+    /// Snap the positions passed to the `Backend` to multiples of this
+    /// size, e.g. `Some(1.0)` for whole device pixels.  Positions are
+    /// snapped as they are emitted, so rounding errors do not accumulate.
+    pub snap: Option<f64>,
+
+    /// Draw the baseline and the math axis across the full width of the
+    /// layout, as thin rules through `Backend::rule`.  Unlike `debug`, this
+    /// draws nothing else, and backends need not implement `bbox`.
+    pub draw_guides: bool,
+
+    /// Uniform scale applied to everything passed to the `Backend`.
+    scale: f64,
 }
 
 #[derive(Copy, Clone, Default)]
@@ -56,23 +85,81 @@ impl Cursor {
 }
 
 pub trait Backend {
+    /// Called once at the start of rendering with the y position of the baseline.
+    fn baseline(&mut self, _y: f64) {}
     fn bbox(&mut self, _pos: Cursor, _width: f64, _height: f64, role: Role) {}
     fn symbol(&mut self, pos: Cursor, gid: u16, scale: f64, ctx: &MathFont);
     fn rule(&mut self, pos: Cursor, width: f64, height: f64);
+    /// A dashed or dotted rule.  By default this is drawn solid.
+    fn rule_styled(&mut self, pos: Cursor, width: f64, height: f64, style: RuleStyle) {
+        self.rule(pos, width, height)
+    }
+    /// A stroked rectangle with the top-left corner at `pos`.  The stroke lies
+    /// inside the rectangle.  By default this is drawn with four rules and
+    /// ignores `radius`.
+    fn frame(&mut self, pos: Cursor, width: f64, height: f64, line_width: f64, radius: f64) {
+        self.rule(pos, width, line_width);
+        self.rule(pos.down(height - line_width), width, line_width);
+        self.rule(pos, line_width, height);
+        self.rule(pos.right(width - line_width), line_width, height);
+    }
+    /// A run of upright glyphs from one font and size, such as a word of
+    /// text, with the origin of its first glyph at the given position.
+    /// Backends which can set text natively may draw the string as one span.
+    /// By default each of `glyphs`, the positions and ids of the glyphs, is
+    /// drawn with `symbol`.
+    fn text_run(&mut self, _pos: Cursor, _text: &str, glyphs: &[(Cursor, u16)], scale: f64, font: &MathFont) {
+        for &(pos, gid) in glyphs {
+            self.symbol(pos, gid, scale, font);
+        }
+    }
     fn begin_color(&mut self, color: RGBA);
     fn end_color(&mut self);
+    /// Draw everything until the matching `end_transform` through
+    /// `transform`, which keeps `origin` in place.  The transform is given
+    /// in the output coordinates.  By default this is ignored, and the
+    /// contents are drawn untransformed.
+    fn begin_transform(&mut self, _origin: Cursor, _transform: AffineTransform) {}
+    fn end_transform(&mut self) {}
+}
+
+/// The number of glyphs at the start of `nodes` which form a text run:
+/// upright glyphs, without an italic correction, of known characters and
+/// the same font and size.
+fn text_run_len(nodes: &[LayoutNode]) -> usize {
+    let upright = |node: &LayoutNode| match node.node {
+        LayoutVariant::Glyph(gly) if gly.codepoint.is_some() && gly.italics.is_zero() => Some(gly),
+        _ => None,
+    };
+    let first = match nodes.first().and_then(upright) {
+        Some(gly) => gly,
+        None => return 0,
+    };
+    nodes.iter()
+        .take_while(|node| upright(node).map_or(false, |gly| {
+            ptr::eq(gly.font, first.font) && gly.size == first.size
+        }))
+        .count()
 }
 
 pub enum Role {
     Glyph,
     VBox,
     HBox,
+    /// A zero-height box along the baseline of the layout.
+    Baseline,
+    /// A zero-height box along the math axis of the layout.
+    Axis,
 }
 
 impl Renderer {
     pub fn new() -> Self {
         Renderer {
             debug: false,
+            y_up: false,
+            snap: None,
+            draw_guides: false,
+            scale: 1.0,
         }
     }
     // pub fn layout<'s, 'a, 'f>(&self, tex: &'s str, layout_settings: LayoutSettings<'a, 'f>) -> Result<Layout<'f>, Error<'s>> {
@@ -91,33 +178,164 @@ impl Renderer {
             layout.height / Px
         )
     }
+    /// The center of `layout` and the bounds `(x0, y0, x1, y1)` it covers
+    /// when rotated by `radians` about that center.  Unlike `size`, these
+    /// are in the output coordinates, as passed to the `Backend`.
+    pub fn rotated_size(&self, layout: &Layout, radians: f64) -> (Cursor, (f64, f64, f64, f64)) {
+        let (w, h, d) = (layout.width / Px, layout.height / Px, layout.depth / Px);
+        let corners = [
+            self.point(Cursor { x: 0.0, y: -h }),
+            self.point(Cursor { x: w, y: -h }),
+            self.point(Cursor { x: 0.0, y: -d }),
+            self.point(Cursor { x: w, y: -d }),
+        ];
+        let center = Cursor {
+            x: (corners[0].x + corners[3].x) * 0.5,
+            y: (corners[0].y + corners[3].y) * 0.5,
+        };
+        let (sin, cos) = radians.sin_cos();
+        let mut bounds = (f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for c in &corners {
+            let (dx, dy) = (c.x - center.x, c.y - center.y);
+            let x = center.x + dx * cos - dy * sin;
+            let y = center.y + dx * sin + dy * cos;
+            bounds.0 = bounds.0.min(x);
+            bounds.1 = bounds.1.min(y);
+            bounds.2 = bounds.2.max(x);
+            bounds.3 = bounds.3.max(y);
+        }
+        (center, bounds)
+    }
     pub fn render(&self, layout: &Layout, out: &mut impl Backend) {
         let pos = Cursor {
             x: 0.0,
             y: 0.0,
         };
+        out.baseline(self.point(pos).y);
         self.render_hbox(
             out,
             pos,
             &layout.contents,
             layout.height / Px,
             layout.width / Px,
-            Alignment::Default
+            layout.alignment
         );
+
+        if self.debug {
+            let width = layout.width / Px;
+            self.bbox(out, pos, width, 0.0, Role::Baseline);
+            self.bbox(out, pos.up(layout.axis_height / Px), width, 0.0, Role::Axis);
+        }
+
+        if self.draw_guides {
+            // Center each rule on its line.
+            const THICKNESS: f64 = 0.1;
+            let width = layout.width / Px;
+            for &y in &[0.0, layout.axis_height / Px] {
+                let pos = pos.up(y + THICKNESS * 0.5);
+                self.rule(out, pos, width, THICKNESS, LayoutRule::default());
+            }
+        }
     }
 
-    fn render_grid(&self, out: &mut impl Backend, pos: Cursor, width: f64, height: f64, grid: &Grid) {
+    /// Render `layout` scaled uniformly by `scale`.
+    pub fn render_scaled(&self, layout: &Layout, out: &mut impl Backend, scale: f64) {
+        Renderer { scale: self.scale * scale, ..*self }.render(layout, out)
+    }
+
+    /// Render `layout` scaled down uniformly so that it fits within
+    /// `max_width` by `max_height`, and return the scale that was used.
+    /// Layouts which already fit are not scaled up; use `render_scaled`
+    /// for that.
+    pub fn render_fit(&self, layout: &Layout, out: &mut impl Backend, max_width: f64, max_height: f64) -> f64 {
+        let (x0, y0, x1, y1) = self.size(layout);
+        let scale = (max_width / (x1 - x0))
+            .min(max_height / (y1 - y0))
+            .min(1.0);
+        self.render_scaled(layout, out, scale);
+        scale
+    }
+
+    fn symbol(&self, out: &mut impl Backend, pos: Cursor, gid: u16, scale: f64, font: &MathFont) {
+        out.symbol(self.snapped(self.point(pos)), gid, scale * self.scale, font)
+    }
+    fn rule(&self, out: &mut impl Backend, pos: Cursor, width: f64, height: f64, rule: LayoutRule) {
+        // Snap both corners, so that adjacent rules still meet.
+        let pos = self.corner(pos, height);
+        let (width, height) = (width * self.scale, height * self.scale);
+        let end = self.snapped(pos.translate(width, height));
+        let pos = self.snapped(pos);
+        let (width, height) = (end.x - pos.x, end.y - pos.y);
+        if let Some(color) = rule.color {
+            out.begin_color(color);
+        }
+        match rule.style {
+            RuleStyle::Solid => out.rule(pos, width, height),
+            style => out.rule_styled(pos, width, height, style),
+        }
+        if rule.color.is_some() {
+            out.end_color();
+        }
+    }
+    fn bbox(&self, out: &mut impl Backend, pos: Cursor, width: f64, height: f64, role: Role) {
+        out.bbox(self.corner(pos, height), width * self.scale, height * self.scale, role)
+    }
+
+    fn snapped(&self, pos: Cursor) -> Cursor {
+        match self.snap {
+            Some(grid) => Cursor {
+                x: (pos.x / grid).round() * grid,
+                y: (pos.y / grid).round() * grid,
+            },
+            None => pos,
+        }
+    }
+
+    /// Convert a point from the y-down layout coordinates to the output convention.
+    fn point(&self, pos: Cursor) -> Cursor {
+        let (x, y) = (pos.x * self.scale, pos.y * self.scale);
+        match self.y_up {
+            false => Cursor { x, y },
+            true => Cursor { x, y: -y },
+        }
+    }
+
+    /// Convert the top-left corner of a box to the output convention.
+    fn corner(&self, pos: Cursor, height: f64) -> Cursor {
+        let (x, y) = (pos.x * self.scale, pos.y * self.scale);
+        match self.y_up {
+            false => Cursor { x, y },
+            true => Cursor { x, y: -(y + height * self.scale) },
+        }
+    }
+
+    /// Render the contents of `tb`, with its origin at `pos`.
+    fn render_transform(&self, out: &mut impl Backend, pos: Cursor, tb: &TransformBox) {
+        let t = tb.transform;
+        let t = AffineTransform { tx: t.tx * self.scale, ty: t.ty * self.scale, ..t };
+        let t = match self.y_up {
+            true => t,
+            false => t.flip_y(),
+        };
+        out.begin_transform(self.point(pos), t);
+        self.render_node(out, pos, &tb.inner);
+        out.end_transform();
+    }
+
+    /// Render the cells of `grid`, whose top-left corner is at `pos`.  Each cell
+    /// is rendered on the baseline of its row, so colored cells work as they
+    /// do anywhere else.
+    fn render_grid(&self, out: &mut impl Backend, pos: Cursor, _width: f64, _height: f64, grid: &Grid) {
         let x_offsets = grid.x_offsets();
         let y_offsets = grid.y_offsets();
         for (&(row, column), node) in grid.contents.iter() {
-            let width = grid.columns[column];
-            let (height, depth) = grid.rows[row];
+            let (height, _) = grid.rows[&row];
 
             self.render_node(
                 out,
                 pos.translate(
-                    x_offsets[column] / Px,
-                    (y_offsets[row] + height) / Px
+                    x_offsets[&column] / Px,
+                    (y_offsets[&row] + height) / Px
                 ),
                 node
             );
@@ -134,17 +352,50 @@ impl Renderer {
         alignment: Alignment,
     ) {
         if self.debug {
-            out.bbox(pos.up(height), nodes_width, height, Role::HBox);
+            self.bbox(out, pos.up(height), nodes_width, height, Role::HBox);
         }
-        if let Alignment::Centered(w) = alignment {
-            pos.x += (nodes_width - w / Px) * 0.5;
+        match alignment {
+            Alignment::Centered(w) => pos.x += (nodes_width - w / Px) * 0.5,
+            Alignment::Right(w) => pos.x += nodes_width - w / Px,
+            _ => (),
         }
 
-        for node in nodes {
-            self.render_node(out, pos, node);
+        let mut idx = 0;
+        while idx < nodes.len() {
+            let run = match self.debug {
+                true => 1,
+                false => text_run_len(&nodes[idx..]),
+            };
+            if run > 1 {
+                pos = self.text_run(out, pos, &nodes[idx..idx + run]);
+                idx += run;
+                continue;
+            }
+
+            self.render_node(out, pos, &nodes[idx]);
+            pos.x += nodes[idx].width / Px;
+            idx += 1;
+        }
+    }
 
+    /// Emit glyphs found by `text_run_len` as one call to `Backend::text_run`.
+    /// Returns the position after the run.
+    fn text_run(&self, out: &mut impl Backend, mut pos: Cursor, nodes: &[LayoutNode]) -> Cursor {
+        let mut text = String::new();
+        let mut glyphs = Vec::with_capacity(nodes.len());
+        let mut first = None;
+        for node in nodes {
+            if let LayoutVariant::Glyph(ref gly) = node.node {
+                text.extend(gly.codepoint);
+                glyphs.push((self.snapped(self.point(pos)), gly.gid));
+                first = first.or(Some(*gly));
+            }
             pos.x += node.width / Px;
         }
+        if let Some(gly) = first {
+            out.text_run(glyphs[0].0, &text, &glyphs, gly.size / Px * self.scale, gly.font);
+        }
+        pos
     }
     fn render_vbox(
         &self,
@@ -154,14 +405,14 @@ impl Renderer {
     ) {
         for node in nodes {
             match node.node {
-                LayoutVariant::Rule => {
-                    out.rule(pos, node.width / Px, node.height / Px)
+                LayoutVariant::Rule(rule) => {
+                    self.rule(out, pos, node.width / Px, node.height / Px, rule)
                 }
                 LayoutVariant::Grid(ref grid) => {
                     self.render_grid(
                         out,
-                        pos, node.height / Px,
-                        node.width / Px, grid
+                        pos, node.width / Px,
+                        node.height / Px, grid
                     )
                 }
                 LayoutVariant::HorizontalBox(ref hbox) => {
@@ -177,7 +428,8 @@ impl Renderer {
 
                 LayoutVariant::VerticalBox(ref vbox) => {
                     if self.debug {
-                        out.bbox(
+                        self.bbox(
+                            out,
                             pos,
                             node.width / Px,
                             (node.height - node.depth) / Px,
@@ -189,20 +441,25 @@ impl Renderer {
 
                 LayoutVariant::Glyph(ref gly) => {
                     if self.debug {
-                        out.bbox(
+                        self.bbox(
+                            out,
                             pos,
                             node.width / Px,
                             (node.height - node.depth) / Px,
                             Role::Glyph
                         );
                     }
-                    out.symbol(pos.down(node.height / Px), gly.gid, gly.size / Px, gly.font);
+                    self.symbol(out, pos.down(node.height / Px), gly.gid, gly.size / Px, gly.font);
                 }
 
                 LayoutVariant::Color(_) => {
                     panic!("Shouldn't have a color in a vertical box???")
                 }
 
+                LayoutVariant::Transform(ref tb) => {
+                    self.render_transform(out, pos.down(node.height / Px), tb);
+                }
+
                 LayoutVariant::Kern => { /* NOOP */ }
             }
 
@@ -214,24 +471,28 @@ impl Renderer {
         match node.node {
             LayoutVariant::Glyph(ref gly) => {
                 if self.debug {
-                    out.bbox(
+                    self.bbox(
+                        out,
                         pos.up(node.height / Px),
                         node.width / Px, (node.height - node.depth) / Px, Role::Glyph
                     );
                 }
-                out.symbol(pos, gly.gid, gly.size / Px, gly.font);
+                self.symbol(out, pos, gly.gid, gly.size / Px, gly.font);
             }
 
-            LayoutVariant::Rule => {
-                out.rule(
+            LayoutVariant::Rule(rule) => {
+                self.rule(
+                    out,
                     pos.up(node.height / Px),
-                    node.width / Px, node.height / Px
+                    node.width / Px, node.height / Px,
+                    rule
                 )
             }
 
             LayoutVariant::VerticalBox(ref vbox) => {
                 if self.debug {
-                    out.bbox(
+                    self.bbox(
+                        out,
                         pos.up(node.height / Px),
                         node.width / Px,
                         (node.height - node.depth) / Px, Role::VBox
@@ -250,14 +511,17 @@ impl Renderer {
                 );
             }
             LayoutVariant::Grid(ref grid) => {
+                // `render_grid` expects the top-left corner of the grid.
                 self.render_grid(
                     out,
-                    pos,
-                    node.height / Px,
-                    node.width / Px, grid
+                    pos.up(node.height / Px),
+                    node.width / Px,
+                    node.height / Px, grid
                 )
             }
 
+            LayoutVariant::Transform(ref tb) => self.render_transform(out, pos, tb),
+
             LayoutVariant::Color(ref clr) => {
                 out.begin_color(clr.color);
                 self.render_hbox(
@@ -278,4 +542,382 @@ impl Renderer {
 }
 
 pub mod scene;
-pub use scene::SceneWrapper;
+pub use scene::{SceneWrapper, Format, render_to_bytes};
+
+pub mod html;
+pub use html::HtmlBackend;
+
+pub mod counting;
+pub use counting::{NullBackend, CountingBackend};
+
+#[cfg(feature = "wasm")]
+pub mod canvas;
+#[cfg(feature = "wasm")]
+pub use canvas::CanvasBackend;
+
+#[cfg(feature = "tiny-skia")]
+pub mod raster;
+#[cfg(feature = "tiny-skia")]
+pub use raster::TinySkiaBackend;
+
+mod glyphs;
+mod outline;
+pub use outline::OutlineBackend;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+    use crate::ast::nodes::{BarThickness, GenFraction, MathStyle, ParseNode, Rule, Scripts};
+    use crate::font::AtomType;
+    use crate::layout::{engine, ColorChange};
+
+    /// A backend which records the calls made to it.
+    #[derive(Default)]
+    struct Recorder {
+        symbols: Vec<(Cursor, u16)>,
+        rules: Vec<(Cursor, f64, f64)>,
+        events: Vec<&'static str>,
+        baselines: Vec<f64>,
+    }
+
+    impl Backend for Recorder {
+        fn baseline(&mut self, y: f64) {
+            self.baselines.push(y);
+        }
+        fn symbol(&mut self, pos: Cursor, gid: u16, _scale: f64, _font: &MathFont) {
+            self.symbols.push((pos, gid));
+            self.events.push("symbol");
+        }
+        fn rule(&mut self, pos: Cursor, width: f64, height: f64) {
+            self.rules.push((pos, width, height));
+            self.events.push("rule");
+        }
+        fn begin_color(&mut self, _color: RGBA) {
+            self.events.push("begin_color");
+        }
+        fn end_color(&mut self) {
+            self.events.push("end_color");
+        }
+    }
+
+    fn record(renderer: &Renderer, layout: &Layout) -> Recorder {
+        let mut out = Recorder::default();
+        renderer.render(layout, &mut out);
+        out
+    }
+
+    fn x_squared() -> Vec<ParseNode> {
+        vec![ParseNode::Scripts(Scripts {
+            base: Some(Box::new(ord('x'))),
+            superscript: Some(vec![ord('2')]),
+            subscript: None,
+        })]
+    }
+
+    #[test]
+    fn y_up_mirrors_positions() {
+        let layout = engine::layout(&x_squared(), settings(Style::Display)).unwrap();
+        let down = record(&Renderer::new(), &layout);
+        let up = record(&Renderer { y_up: true, ..Renderer::new() }, &layout);
+
+        assert_eq!(down.symbols.len(), 2);
+        for (&(d, _), &(u, _)) in down.symbols.iter().zip(&up.symbols) {
+            assert_close(d.x, u.x);
+            assert_close(d.y, -u.y);
+        }
+        // The superscript is above the baseline.
+        assert!(down.symbols[1].0.y < 0.0);
+    }
+
+    #[test]
+    fn baseline_reported_once() {
+        let config = settings(Style::Text);
+        let layout = engine::layout(&[ord('x')], config).unwrap();
+        let out = record(&Renderer::new(), &layout);
+        assert_eq!(out.baselines, vec![0.0]);
+        // The glyph sits on the reported baseline.
+        assert_close(out.symbols[0].0.y, 0.0);
+
+        let axis = config.ctx.constants.axis_height * config.font_size;
+        assert_close(layout.axis_height / Px, axis / Px);
+    }
+
+    #[test]
+    fn snapped_positions_are_whole() {
+        let nodes = [ParseNode::Overline(x_squared())];
+        let layout = engine::layout(&nodes, settings(Style::Display)).unwrap();
+        let out = record(&Renderer { snap: Some(1.0), ..Renderer::new() }, &layout);
+
+        let whole = |v: f64| v == v.round();
+        assert_eq!(out.symbols.len(), 2);
+        for &(pos, _) in &out.symbols {
+            assert!(whole(pos.x) && whole(pos.y), "({}, {})", pos.x, pos.y);
+        }
+        assert_eq!(out.rules.len(), 1);
+        for &(pos, width, height) in &out.rules {
+            assert!(whole(pos.x) && whole(pos.y) && whole(width) && whole(height));
+        }
+    }
+
+    #[test]
+    fn colored_rule_sets_its_color() {
+        let rule = |color| ParseNode::Rule(Rule { width: Unit::Em(1.0), height: Unit::Em(0.1), color });
+        let config = settings(Style::Text);
+
+        let plain = record(&Renderer::new(), &engine::layout(&[rule(None)], config).unwrap());
+        assert_eq!(plain.events, vec!["rule"]);
+
+        let red = rule(Some(RGBA(0xff, 0, 0, 0xff)));
+        let colored = record(&Renderer::new(), &engine::layout(&[red], config).unwrap());
+        assert_eq!(colored.events, vec!["begin_color", "rule", "end_color"]);
+    }
+
+    #[test]
+    fn colored_grid_cell() {
+        let config = settings(Style::Text);
+        let cell = |cp| engine::layout(&[ord(cp)], config).unwrap();
+        let grid = |colored: bool| {
+            let mut grid = Grid::new();
+            grid.insert(0, 0, cell('a').as_node());
+            grid.insert(0, 1, cell('b').as_node());
+            grid.insert(1, 0, cell('c').as_node());
+            let mut node = cell('d').as_node();
+            if colored {
+                let inner = vec![node.clone()];
+                let color = ColorChange { color: RGBA(0, 0, 0xff, 0xff), inner };
+                node.node = LayoutVariant::Color(color);
+            }
+            grid.insert(1, 1, node);
+            let mut layout = Layout::new();
+            layout.add_node(grid.build());
+            record(&Renderer::new(), &layout)
+        };
+
+        let plain = grid(false);
+        let colored = grid(true);
+        assert_eq!(colored.events, vec!["symbol", "symbol", "symbol", "begin_color", "symbol", "end_color"]);
+        // The colored cell is placed as it would be without the color.
+        assert_eq!(plain.symbols.len(), colored.symbols.len());
+        for (&(p, _), &(c, _)) in plain.symbols.iter().zip(&colored.symbols) {
+            assert_close(p.x, c.x);
+            assert_close(p.y, c.y);
+        }
+    }
+
+    #[test]
+    fn fit_scales_down() {
+        let nodes: Vec<_> = "abcdefgh".chars().map(ord).collect();
+        let layout = engine::layout(&nodes, settings(Style::Text)).unwrap();
+        let renderer = Renderer::new();
+        let (x0, y0, x1, y1) = renderer.size(&layout);
+        let plain = record(&renderer, &layout);
+
+        let mut fitted = Recorder::default();
+        let scale = renderer.render_fit(&layout, &mut fitted, 20.0, 100.0);
+        assert!(scale < 1.0);
+        assert_close(scale * (x1 - x0), 20.0);
+        for (&(p, _), &(f, _)) in plain.symbols.iter().zip(&fitted.symbols) {
+            assert_close(f.x, p.x * scale);
+            assert_close(f.y, p.y * scale);
+        }
+
+        // Layouts which fit are left alone.
+        let mut out = Recorder::default();
+        assert_eq!(renderer.render_fit(&layout, &mut out, 2.0 * (x1 - x0), 2.0 * (y1 - y0)), 1.0);
+    }
+
+    #[test]
+    fn fraction_bar_follows_axis() {
+        let fraction = ParseNode::GenFraction(GenFraction {
+            numerator: vec![ord('a')],
+            denominator: vec![ord('b')],
+            bar_thickness: BarThickness::Default,
+            left_delimiter: None,
+            right_delimiter: None,
+            style: MathStyle::NoChange,
+            bar_padding: Unit::Em(0.0),
+        });
+        // The center of the fraction bar above the baseline.
+        let bar_center = |axis_height: f64| {
+            let mut ctx = ctx();
+            ctx.constants.axis_height = Length::new(axis_height, Em);
+            let config = LayoutSettings::new(Box::leak(Box::new(ctx)), 10.0, Style::Text);
+            let layout = engine::layout(&[fraction.clone()], config).unwrap();
+            let out = record(&Renderer::new(), &layout);
+            assert_eq!(out.rules.len(), 1);
+            let (pos, _, height) = out.rules[0];
+            -(pos.y + height * 0.5)
+        };
+
+        assert_close(bar_center(0.25), 2.5);
+        assert_close(bar_center(0.4), 4.0);
+    }
+
+    #[test]
+    fn guides_add_two_rules() {
+        let config = settings(Style::Text);
+        let layout = engine::layout(&x_squared(), config).unwrap();
+        let plain = record(&Renderer::new(), &layout);
+        let guided = record(&Renderer { draw_guides: true, ..Renderer::new() }, &layout);
+
+        assert!(plain.rules.is_empty());
+        assert_eq!(guided.symbols.len(), plain.symbols.len());
+        assert_eq!(guided.rules.len(), 2);
+        let axis = layout.axis_height / Px;
+        for (&(pos, width, height), &line) in guided.rules.iter().zip(&[0.0, axis]) {
+            assert_close(pos.x, 0.0);
+            assert_close(width, layout.width / Px);
+            // Centered on the baseline or the axis.
+            assert_close(-(pos.y + height * 0.5), line);
+        }
+    }
+
+    #[test]
+    fn color_switch_colors_rest_of_group() {
+        let config = settings(Style::Text);
+        let red = ParseNode::ColorSwitch(RGBA(0xff, 0, 0, 0xff));
+        let group = ParseNode::Group(vec![ord('a'), red.clone(), ord('b'), ord('c')]);
+        let layout = engine::layout(&[group, ord('d')], config).unwrap();
+        let out = record(&Renderer::new(), &layout);
+        assert_eq!(out.events, vec!["symbol", "begin_color", "symbol", "symbol", "end_color", "symbol"]);
+
+        // The atom after the switch is spaced against the one before it.
+        let plus = sym('+', AtomType::Binary);
+        let plain = engine::layout(&[ord('a'), plus.clone(), ord('b')], config).unwrap();
+        let switched = engine::layout(&[ord('a'), red, plus, ord('b')], config).unwrap();
+        assert_close(switched.width / Px, plain.width / Px);
+    }
+
+    #[test]
+    fn padded_fraction_bar() {
+        let config = settings(Style::Display);
+        let bar_width = |padding| {
+            let fraction = ParseNode::GenFraction(GenFraction {
+                numerator: vec![ord('a')],
+                denominator: vec![ord('b'), ord('c')],
+                bar_thickness: BarThickness::Default,
+                left_delimiter: None,
+                right_delimiter: None,
+                style: MathStyle::NoChange,
+                bar_padding: Unit::Em(padding),
+            });
+            let out = record(&Renderer::new(), &engine::layout(&[fraction], config).unwrap());
+            out.rules[0].1
+        };
+
+        let bc = engine::layout(&[ord('b'), ord('c')], config).unwrap();
+        assert_close(bar_width(0.0), bc.width / Px);
+        assert_close(bar_width(0.25), bc.width / Px + 5.0);
+    }
+
+    #[test]
+    fn upright_run_is_one_call() {
+        /// Records text runs, and glyphs drawn on their own.
+        #[derive(Default)]
+        struct Runs {
+            runs: Vec<(String, usize)>,
+            symbols: usize,
+        }
+        impl Backend for Runs {
+            fn symbol(&mut self, _pos: Cursor, _gid: u16, _scale: f64, _font: &MathFont) {
+                self.symbols += 1;
+            }
+            fn rule(&mut self, _pos: Cursor, _width: f64, _height: f64) {}
+            fn text_run(&mut self, _pos: Cursor, text: &str, glyphs: &[(Cursor, u16)], _scale: f64, _font: &MathFont) {
+                self.runs.push((text.to_string(), glyphs.len()));
+            }
+            fn begin_color(&mut self, _color: RGBA) {}
+            fn end_color(&mut self) {}
+        }
+
+        let layout = engine::layout(&[ord('1'), ord('2'), ord('3')], settings(Style::Text)).unwrap();
+        let mut out = Runs::default();
+        Renderer::new().render(&layout, &mut out);
+        assert_eq!(out.runs, vec![("123".to_string(), 3)]);
+        assert_eq!(out.symbols, 0);
+
+        // Backends without `text_run` still see every glyph.
+        assert_eq!(record(&Renderer::new(), &layout).symbols.len(), 3);
+    }
+
+    #[test]
+    fn bar_thickness_is_clamped() {
+        let rules = |thickness| {
+            let fraction = ParseNode::GenFraction(GenFraction {
+                numerator: vec![ord('a')],
+                denominator: vec![ord('b')],
+                bar_thickness: BarThickness::Unit(Unit::Em(thickness)),
+                left_delimiter: None,
+                right_delimiter: None,
+                style: MathStyle::NoChange,
+                bar_padding: Unit::Em(0.0),
+            });
+            let layout = engine::layout(&[fraction], settings(Style::Display)).unwrap();
+            record(&Renderer::new(), &layout).rules
+        };
+
+        // A hundredth of a pixel is drawn one pixel thick.
+        let thin = rules(0.001);
+        assert_eq!(thin.len(), 1);
+        assert_close(thin[0].2, 1.0);
+
+        assert!(rules(-0.1).is_empty());
+    }
+
+    #[test]
+    fn overline_covers_scripts() {
+        let config = settings(Style::Text);
+        let x = engine::layout(&[ord('x')], config).unwrap();
+        let scripted = engine::layout(&x_squared(), config).unwrap();
+        let lines = [ParseNode::Overline(x_squared()), ParseNode::Underline(x_squared())];
+        for line in lines.iter() {
+            let out = record(&Renderer::new(), &engine::layout(&[line.clone()], config).unwrap());
+            assert_eq!(out.rules.len(), 1);
+            let (pos, width, _) = out.rules[0];
+            assert_close(pos.x, 0.0);
+            assert_close(width, scripted.width / Px);
+            assert!(width > x.width / Px);
+        }
+    }
+
+    #[test]
+    fn quarter_turn_swaps_extent() {
+        let renderer = Renderer::new();
+        let layout = engine::layout(&[ord('a'), ord('b'), ord('c'), ord('g')], settings(Style::Text)).unwrap();
+        let extent = |radians: f64| {
+            let (_, (x0, y0, x1, y1)) = renderer.rotated_size(&layout, radians);
+            (x1 - x0, y1 - y0)
+        };
+
+        let (width, height) = extent(0.0);
+        assert_close(width, layout.width / Px);
+        assert_close(height, (layout.height - layout.depth) / Px);
+
+        let (turned_width, turned_height) = extent(::std::f64::consts::FRAC_PI_2);
+        assert_close(turned_width, height);
+        assert_close(turned_height, width);
+    }
+
+    #[test]
+    fn bar_spans_wider_denominator() {
+        use crate::ast::symbols::Symbol;
+        let config = settings(Style::Display);
+        let fraction = ParseNode::GenFraction(GenFraction {
+            numerator: vec![ord('a')],
+            denominator: vec![ord('b'), ord('c'), ord('d')],
+            bar_thickness: BarThickness::Default,
+            left_delimiter: Some(Symbol { codepoint: '(', atom_type: AtomType::Open }),
+            right_delimiter: Some(Symbol { codepoint: ')', atom_type: AtomType::Close }),
+            style: MathStyle::NoChange,
+            bar_padding: Unit::Em(0.0),
+        });
+        let out = record(&Renderer::new(), &engine::layout(&[fraction], config).unwrap());
+
+        // The bar follows the left delimiter, and is as wide as `bcd`.
+        let bcd = engine::layout(&[ord('b'), ord('c'), ord('d')], config).unwrap();
+        let (pos, width, _) = out.rules[0];
+        assert_close(width, bcd.width / Px);
+        assert!(pos.x > 0.0);
+    }
+}
@@ -0,0 +1,141 @@
+//! Decompose pathfinder outlines into path commands, for backends which
+//! do not draw through a pathfinder `Scene`, and collect a whole formula
+//! into a single `Outline`.
+
+use pathfinder_content::{
+    outline::{Outline, ContourIterFlags},
+    segment::SegmentKind,
+};
+use pathfinder_geometry::{
+    transform2d::Transform2F,
+    vector::Vector2F,
+    rect::RectF,
+};
+use super::{Backend, Cursor};
+use super::scene::transform_about;
+use crate::font::MathFont;
+use crate::dimensions::AffineTransform;
+use crate::ast::color::RGBA;
+
+pub trait PathSink {
+    fn move_to(&mut self, p: Vector2F);
+    fn line_to(&mut self, p: Vector2F);
+    fn quad_to(&mut self, c: Vector2F, p: Vector2F);
+    fn cubic_to(&mut self, c0: Vector2F, c1: Vector2F, p: Vector2F);
+    fn close(&mut self);
+}
+
+/// Feed the contours of `outline`, transformed by `tr`, into `sink`.
+pub fn decompose(outline: &Outline, tr: Transform2F, sink: &mut impl PathSink) {
+    for contour in outline.contours() {
+        for (i, segment) in contour.iter(ContourIterFlags::empty()).enumerate() {
+            if i == 0 {
+                sink.move_to(tr * segment.baseline.from());
+            }
+            let to = tr * segment.baseline.to();
+            match segment.kind {
+                SegmentKind::None => {}
+                SegmentKind::Line => sink.line_to(to),
+                SegmentKind::Quadratic => sink.quad_to(tr * segment.ctrl.from(), to),
+                SegmentKind::Cubic => sink.cubic_to(tr * segment.ctrl.from(), tr * segment.ctrl.to(), to),
+            }
+        }
+        if contour.is_closed() {
+            sink.close();
+        }
+    }
+}
+
+/// A backend merging the outlines of all glyphs and rules into one `Outline`,
+/// for callers with a renderer of their own.  Colors are dropped.  Like the
+/// `SceneWrapper`, this expects `Renderer::y_up` to be `false`.
+pub struct OutlineBackend {
+    outline: Outline,
+    transform: Transform2F,
+    transform_stack: Vec<Transform2F>,
+}
+
+impl OutlineBackend {
+    pub fn new() -> Self {
+        OutlineBackend::with_transform(Transform2F::default())
+    }
+    pub fn with_transform(transform: Transform2F) -> Self {
+        OutlineBackend {
+            outline: Outline::new(),
+            transform,
+            transform_stack: Vec::new(),
+        }
+    }
+    pub fn into_outline(self) -> Outline {
+        self.outline
+    }
+
+    fn push(&mut self, outline: &Outline) {
+        for contour in outline.contours() {
+            self.outline.push_contour(contour.clone());
+        }
+    }
+}
+
+impl Backend for OutlineBackend {
+    fn symbol(&mut self, pos: Cursor, gid: u16, scale: f64, font: &MathFont) {
+        use font::{Font, GlyphId};
+        let path = font.glyph(GlyphId(gid as u32)).unwrap().path;
+        let tr = self.transform
+            * Transform2F::from_translation(Vector2F::new(pos.x as f32, pos.y as f32))
+            * Transform2F::from_scale(Vector2F::new(scale as f32, -scale as f32))
+            * font.font_matrix();
+        self.push(&path.transformed(&tr));
+    }
+    fn rule(&mut self, pos: Cursor, width: f64, height: f64) {
+        let rect = RectF::new(
+            Vector2F::new(pos.x as f32, pos.y as f32),
+            Vector2F::new(width as f32, height as f32)
+        );
+        self.push(&Outline::from_rect(rect).transformed(&self.transform));
+    }
+    fn begin_color(&mut self, _color: RGBA) {}
+    fn end_color(&mut self) {}
+    fn begin_transform(&mut self, origin: Cursor, transform: AffineTransform) {
+        self.transform_stack.push(self.transform);
+        self.transform = self.transform * transform_about(origin, transform);
+    }
+    fn end_transform(&mut self) {
+        self.transform = self.transform_stack.pop().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+    use crate::ast::nodes::{ParseNode, RaiseBox, Rule};
+    use crate::dimensions::Unit;
+    use crate::layout::{engine, Style};
+    use crate::render::Renderer;
+
+    fn assert_near(a: f32, b: f64) {
+        assert!((a as f64 - b).abs() < 1e-3, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn bounds_match_size() {
+        // A rule lowered below the baseline, whose ink fills its box.
+        let rule = ParseNode::Rule(Rule { width: Unit::Em(3.0), height: Unit::Em(1.0), color: None });
+        let lowered = ParseNode::RaiseBox(RaiseBox { amount: Unit::Em(-0.5), inner: vec![rule] });
+        let layout = engine::layout(&[lowered], settings(Style::Text)).unwrap();
+
+        let renderer = Renderer::new();
+        let mut out = OutlineBackend::new();
+        renderer.render(&layout, &mut out);
+        let bounds = out.into_outline().bounds();
+
+        // `size` is y-up, while the outline is y-down.
+        let (x0, y0, x1, y1) = renderer.size(&layout);
+        assert_near(bounds.min_x(), x0);
+        assert_near(bounds.max_x(), x1);
+        assert_near(bounds.min_y(), -y1);
+        assert_near(bounds.max_y(), -y0);
+        assert_near(bounds.max_y(), 5.0);
+    }
+}
@@ -0,0 +1,229 @@
+use tiny_skia::{Pixmap, PixmapPaint, Paint, PathBuilder, FillRule, FilterQuality, Rect, Transform};
+use pathfinder_geometry::{
+    transform2d::Transform2F,
+    vector::Vector2F,
+};
+use super::{Backend, Cursor};
+use super::outline::{decompose, PathSink};
+use crate::font::MathFont;
+use crate::ast::color::RGBA;
+use crate::dimensions::AffineTransform;
+
+fn v_xy(x: f64, y: f64) -> Vector2F {
+    Vector2F::new(x as f32, y as f32)
+}
+
+/// A backend filling glyphs and rules onto a `tiny_skia::Pixmap`, without
+/// going through the pathfinder renderer.  Pixmaps use y-down coordinates,
+/// so this expects `Renderer::y_up` to be `false`.
+pub struct TinySkiaBackend<'a> {
+    pixmap: &'a mut Pixmap,
+    transform: Transform,
+    transform_stack: Vec<Transform>,
+    color_stack: Vec<RGBA>,
+    color: RGBA,
+    /// The supersampled buffer and its scale, if oversampling.
+    buffer: Option<(Pixmap, u32)>,
+}
+
+impl<'a> TinySkiaBackend<'a> {
+    pub fn new(pixmap: &'a mut Pixmap, transform: Transform) -> Self {
+        TinySkiaBackend {
+            pixmap,
+            transform,
+            transform_stack: Vec::new(),
+            color_stack: Vec::new(),
+            color: RGBA(0, 0, 0, 0xff),
+            buffer: None,
+        }
+    }
+
+    /// Draw into a buffer `factor` times the size of `pixmap` in each
+    /// direction, which is scaled down onto `pixmap` by `finish`.  This
+    /// smooths small glyphs, at the cost of `factor²` the memory and fill
+    /// time.  A factor of 1 draws directly, like `new`.
+    pub fn with_oversampling(pixmap: &'a mut Pixmap, transform: Transform, factor: u32) -> Self {
+        let buffer = match factor {
+            0 | 1 => None,
+            _ => Pixmap::new(pixmap.width() * factor, pixmap.height() * factor)
+                .map(|buffer| (buffer, factor)),
+        };
+        let transform = match buffer {
+            Some(_) => transform.post_scale(factor as f32, factor as f32).unwrap_or(transform),
+            None => transform,
+        };
+        TinySkiaBackend {
+            buffer,
+            ..TinySkiaBackend::new(pixmap, transform)
+        }
+    }
+
+    /// Scale the oversampled buffer, if any, down onto the target pixmap.
+    /// Without oversampling, everything has already been drawn.
+    pub fn finish(self) {
+        if let Some((buffer, factor)) = self.buffer {
+            let paint = PixmapPaint {
+                quality: FilterQuality::Bicubic,
+                ..PixmapPaint::default()
+            };
+            let scale = 1.0 / factor as f32;
+            let transform = Transform::from_scale(scale, scale).unwrap_or_default();
+            self.pixmap.draw_pixmap(0, 0, buffer.as_ref(), &paint, transform, None);
+        }
+    }
+
+    fn target(&mut self) -> &mut Pixmap {
+        match self.buffer {
+            Some((ref mut buffer, _)) => buffer,
+            None => &mut *self.pixmap,
+        }
+    }
+
+    fn paint(&self) -> Paint<'static> {
+        let RGBA(r, g, b, a) = self.color;
+        let mut paint = Paint::default();
+        paint.set_color_rgba8(r, g, b, a);
+        paint.anti_alias = true;
+        paint
+    }
+}
+
+impl PathSink for PathBuilder {
+    fn move_to(&mut self, p: Vector2F) {
+        PathBuilder::move_to(self, p.x(), p.y());
+    }
+    fn line_to(&mut self, p: Vector2F) {
+        PathBuilder::line_to(self, p.x(), p.y());
+    }
+    fn quad_to(&mut self, c: Vector2F, p: Vector2F) {
+        PathBuilder::quad_to(self, c.x(), c.y(), p.x(), p.y());
+    }
+    fn cubic_to(&mut self, c0: Vector2F, c1: Vector2F, p: Vector2F) {
+        PathBuilder::cubic_to(self, c0.x(), c0.y(), c1.x(), c1.y(), p.x(), p.y());
+    }
+    fn close(&mut self) {
+        PathBuilder::close(self);
+    }
+}
+
+impl<'a> Backend for TinySkiaBackend<'a> {
+    fn symbol(&mut self, pos: Cursor, gid: u16, scale: f64, font: &MathFont) {
+        use font::{Font, GlyphId};
+        let outline = font.glyph(GlyphId(gid as u32)).unwrap().path;
+        let tr = Transform2F::from_translation(v_xy(pos.x, pos.y))
+            * Transform2F::from_scale(v_xy(scale, -scale))
+            * font.font_matrix();
+
+        let mut builder = PathBuilder::new();
+        decompose(&outline, tr, &mut builder);
+
+        // Glyphs without contours, such as spaces, produce no path.
+        if let Some(path) = builder.finish() {
+            let (paint, transform) = (self.paint(), self.transform);
+            self.target().fill_path(&path, &paint, FillRule::Winding, transform, None);
+        }
+    }
+    fn rule(&mut self, pos: Cursor, width: f64, height: f64) {
+        if let Some(rect) = Rect::from_xywh(pos.x as f32, pos.y as f32, width as f32, height as f32) {
+            let (paint, transform) = (self.paint(), self.transform);
+            self.target().fill_rect(rect, &paint, transform, None);
+        }
+    }
+    fn begin_color(&mut self, color: RGBA) {
+        self.color_stack.push(self.color);
+        self.color = color;
+    }
+    fn end_color(&mut self) {
+        self.color = self.color_stack.pop().unwrap();
+    }
+    fn begin_transform(&mut self, origin: Cursor, t: AffineTransform) {
+        self.transform_stack.push(self.transform);
+
+        // `t` about `origin`, applied before the transform of the pixmap.
+        // A degenerate transform, which tiny-skia refuses, leaves the
+        // contents untransformed.
+        let (ox, oy) = (origin.x, origin.y);
+        let about = Transform::from_row(
+            t.xx as f32, t.yx as f32, t.xy as f32, t.yy as f32,
+            (t.tx + ox - t.xx * ox - t.xy * oy) as f32,
+            (t.ty + oy - t.yx * ox - t.yy * oy) as f32
+        );
+        if let Some(transform) = about.and_then(|about| self.transform.pre_concat(&about)) {
+            self.transform = transform;
+        }
+    }
+    fn end_transform(&mut self) {
+        self.transform = self.transform_stack.pop().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cmp::{min, max};
+    use crate::testing::*;
+    use crate::layout::{engine, Style};
+    use crate::render::Renderer;
+
+    /// The bounds `(x0, y0, x1, y1)` of the painted pixels of `pixmap`.
+    fn painted(pixmap: &Pixmap) -> Option<(u32, u32, u32, u32)> {
+        let width = pixmap.width();
+        pixmap.pixels().iter().enumerate()
+            .filter(|(_, pixel)| pixel.alpha() > 0)
+            .map(|(i, _)| (i as u32 % width, i as u32 / width))
+            .fold(None, |bounds, (x, y)| Some(match bounds {
+                Some((x0, y0, x1, y1)) => (min(x0, x), min(y0, y), max(x1, x), max(y1, y)),
+                None => (x, y, x, y),
+            }))
+    }
+
+    #[test]
+    fn glyph_is_filled() {
+        let layout = engine::layout(&[ord('x')], settings(Style::Text)).unwrap();
+        let mut pixmap = Pixmap::new(30, 30).unwrap();
+        let mut backend = TinySkiaBackend::new(&mut pixmap, Transform::from_translate(10.0, 20.0).unwrap());
+        Renderer::new().render(&layout, &mut backend);
+        backend.finish();
+
+        // The glyph sits on the baseline at y = 20, starting at x = 10,
+        // and is about half an em across.
+        let (x0, y0, x1, y1) = painted(&pixmap).expect("nothing was drawn");
+        assert!(x0 >= 9 && x1 <= 17, "x: {}..{}", x0, x1);
+        assert!(y0 >= 14 && y1 <= 20, "y: {}..{}", y0, y1);
+    }
+
+    #[test]
+    fn oversampling_buffer() {
+        let transform = Transform::from_translate(10.0, 20.0).unwrap();
+        let mut pixmap = Pixmap::new(30, 30).unwrap();
+        assert!(TinySkiaBackend::with_oversampling(&mut pixmap, transform, 1).buffer.is_none());
+
+        let backend = TinySkiaBackend::with_oversampling(&mut pixmap, transform, 4);
+        let (width, height) = backend.buffer.as_ref().map(|(buffer, _)| (buffer.width(), buffer.height())).unwrap();
+        assert_eq!((width, height), (120, 120));
+        drop(backend);
+
+        // The result lands in the same place as without oversampling.
+        let layout = engine::layout(&[ord('x')], settings(Style::Text)).unwrap();
+        let mut backend = TinySkiaBackend::with_oversampling(&mut pixmap, transform, 4);
+        Renderer::new().render(&layout, &mut backend);
+        backend.finish();
+        let (x0, y0, x1, y1) = painted(&pixmap).expect("nothing was drawn");
+        assert!(x0 >= 9 && x1 <= 17, "x: {}..{}", x0, x1);
+        assert!(y0 >= 14 && y1 <= 20, "y: {}..{}", y0, y1);
+    }
+
+    #[test]
+    fn transform_about_origin() {
+        let mut pixmap = Pixmap::new(30, 30).unwrap();
+        let mut backend = TinySkiaBackend::new(&mut pixmap, Transform::default());
+        backend.begin_transform(Cursor { x: 1.0, y: 1.0 }, AffineTransform::scale(2.0, 2.0));
+        backend.rule(Cursor { x: 2.0, y: 2.0 }, 4.0, 2.0);
+        backend.end_transform();
+        assert!(backend.transform_stack.is_empty());
+        backend.finish();
+
+        // The rule covers 2..6 by 2..4, scaled about (1, 1).
+        assert_eq!(painted(&pixmap), Some((3, 3, 10, 6)));
+    }
+}
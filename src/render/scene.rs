@@ -1,10 +1,11 @@
 use pathfinder_renderer::{
-    scene::{Scene, DrawPath},
+    scene::{Scene, DrawPath, ClipPath, ClipPathId},
     paint::{Paint, PaintId},
 };
 use pathfinder_content::{
-    outline::{Outline},
+    outline::{Outline, Contour},
     stroke::{StrokeStyle, LineCap, LineJoin, OutlineStrokeToFill},
+    dash::OutlineDash,
 };
 use pathfinder_geometry::{
     transform2d::Transform2F,
@@ -12,9 +13,13 @@ use pathfinder_geometry::{
     rect::RectF,
 };
 use pathfinder_color::ColorU;
-use super::{Backend, Cursor, Role};
+use pathfinder_export::{Export, FileFormat};
+use super::{Backend, Cursor, Role, Renderer};
 use crate::font::MathFont;
-use crate::ast::{color::RGBA};
+use crate::layout::{RuleStyle, Layout, LayoutSettings, engine};
+use crate::ast::{color::RGBA, nodes::ParseNode};
+use crate::error::Error;
+use crate::dimensions::AffineTransform;
 use font;
 
 fn v_cursor(c: Cursor) -> Vector2F {
@@ -24,11 +29,33 @@ fn v_xy(x: f64, y: f64) -> Vector2F {
     Vector2F::new(x as f32, y as f32)
 }
 
+/// `transform` as a pathfinder transform which keeps `origin` in place.
+pub(crate) fn transform_about(origin: Cursor, transform: AffineTransform) -> Transform2F {
+    let t = transform;
+    let origin = v_cursor(origin);
+    Transform2F::from_translation(origin)
+        * Transform2F::row_major(
+            t.xx as f32, t.xy as f32, t.tx as f32,
+            t.yx as f32, t.yy as f32, t.ty as f32
+        )
+        * Transform2F::from_translation(-origin)
+}
+
+/// A backend drawing into a pathfinder `Scene`.  Pathfinder uses a y-down
+/// coordinate system, so this expects `Renderer::y_up` to be `false`.
 pub struct SceneWrapper<'a> {
     scene: &'a mut Scene,
-    color_stack: Vec<PaintId>,
+    color_stack: Vec<(PaintId, ColorU)>,
+    transform_stack: Vec<Transform2F>,
     transform: Transform2F,
-    paint: PaintId
+    clip: Option<(RectF, ClipPathId)>,
+    paint: PaintId,
+    color: ColorU,
+
+    /// The stroke of the boxes drawn by `Renderer::debug`, in the units of
+    /// the scene before the transform.  Scale the line width with the font
+    /// size to keep the boxes visible.
+    pub debug_stroke: StrokeStyle,
 }
 impl<'a> SceneWrapper<'a> {
     pub fn new(scene: &'a mut Scene) -> Self {
@@ -37,31 +64,81 @@ impl<'a> SceneWrapper<'a> {
     pub fn with_transform(scene: &'a mut Scene, transform: Transform2F) -> Self {
         SceneWrapper {
             paint: scene.push_paint(&Paint::black()),
+            color: ColorU::black(),
+            debug_stroke: StrokeStyle {
+                line_cap: LineCap::Square,
+                line_join: LineJoin::Bevel,
+                line_width: 0.1,
+            },
             scene,
             color_stack: Vec::new(),
-            transform
+            transform_stack: Vec::new(),
+            transform,
+            clip: None,
+        }
+    }
+    /// Like `with_transform`, but only draw within `clip`, given in the
+    /// coordinates of the scene.  Paths entirely outside of it are skipped.
+    pub fn with_transform_and_clip(scene: &'a mut Scene, transform: Transform2F, clip: RectF) -> Self {
+        let clip_path = scene.push_clip_path(ClipPath::new(Outline::from_rect(clip)));
+        SceneWrapper {
+            clip: Some((clip, clip_path)),
+            ..SceneWrapper::with_transform(scene, transform)
         }
     }
+    fn draw(&mut self, outline: Outline, paint: PaintId) {
+        let mut path = match self.clip {
+            Some((rect, _)) if !rect.intersects(outline.bounds()) => return,
+            _ => DrawPath::new(outline, paint),
+        };
+        if let Some((_, clip_path)) = self.clip {
+            path.set_clip_path(Some(clip_path));
+        }
+        self.scene.push_draw_path(path);
+    }
+}
+
+impl Renderer {
+    /// Render `layout` rotated by `radians` about its center, and return the
+    /// bounds of the result as `rotated_size` does.  The rotation is applied
+    /// before the transform of `out`.
+    pub fn render_rotated(&self, layout: &Layout, out: &mut SceneWrapper, radians: f64) -> (f64, f64, f64, f64) {
+        let (center, bounds) = self.rotated_size(layout, radians);
+        let center = v_cursor(center);
+        let transform = out.transform;
+        out.transform = transform
+            * Transform2F::from_translation(center)
+            * Transform2F::from_rotation(radians as f32)
+            * Transform2F::from_translation(-center);
+        self.render(layout, out);
+        out.transform = transform;
+        bounds
+    }
 }
 
 impl<'a> Backend for SceneWrapper<'a> {
     fn bbox(&mut self, pos: Cursor, width: f64, height: f64, role: Role) {
-        let color = match role {
-            Role::Glyph => ColorU::new(0, 200, 0, 255),
-            Role::HBox => ColorU::new(200, 0, 0, 255),
-            Role::VBox => ColorU::new(0, 0, 200, 255),
+        // Each role is given its own color and dash pattern,
+        // so that overlapping boxes can be told apart.
+        let (color, dashes): (_, &[f32]) = match role {
+            Role::Glyph => (ColorU::new(0, 200, 0, 255), &[]),
+            Role::HBox => (ColorU::new(200, 0, 0, 255), &[0.4, 0.2]),
+            Role::VBox => (ColorU::new(0, 0, 200, 255), &[0.1, 0.2]),
+            Role::Baseline => (ColorU::new(200, 0, 200, 255), &[1.0, 0.3]),
+            Role::Axis => (ColorU::new(0, 200, 200, 255), &[1.0, 0.3, 0.1, 0.3]),
         };
         let paint = self.scene.push_paint(&Paint::from_color(color));
-        let style = StrokeStyle {
-            line_cap: LineCap::Square,
-            line_join: LineJoin::Bevel,
-            line_width: 0.1
-        };
-        let outline = Outline::from_rect(RectF::new(v_cursor(pos), v_xy(width, height)));
+        let style = self.debug_stroke;
+        let mut outline = Outline::from_rect(RectF::new(v_cursor(pos), v_xy(width, height)));
+        if !dashes.is_empty() {
+            let mut dash = OutlineDash::new(&outline, dashes, 0.0);
+            dash.dash();
+            outline = dash.into_outline();
+        }
         let mut stroke = OutlineStrokeToFill::new(&outline, style);
         stroke.offset();
         let outline = stroke.into_outline().transformed(&self.transform);
-        self.scene.push_draw_path(DrawPath::new(outline, paint));
+        self.draw(outline, paint);
     }
     fn symbol(&mut self, pos: Cursor, gid: u16, scale: f64, font: &MathFont) {
         use font::{Font, GlyphId};
@@ -71,20 +148,257 @@ impl<'a> Backend for SceneWrapper<'a> {
             * Transform2F::from_scale(v_xy(scale, -scale))
             * font.font_matrix();
         
-        self.scene.push_draw_path(DrawPath::new(path.transformed(&tr), self.paint));
+        self.draw(path.transformed(&tr), self.paint);
     }
     fn rule(&mut self, pos: Cursor, width: f64, height: f64) {
         let origin = v_cursor(pos);
         let size = v_xy(width, height);
 
         let outline = Outline::from_rect(RectF::new(origin, size));
-        self.scene.push_draw_path(DrawPath::new(outline.transformed(&self.transform), self.paint));
+        self.draw(outline.transformed(&self.transform), self.paint);
+    }
+    fn rule_styled(&mut self, pos: Cursor, width: f64, height: f64, style: RuleStyle) {
+        // Dash the center line of the rule along its longer side,
+        // and stroke it with the thickness of the rule.
+        let (from, to, thickness) = if width >= height {
+            (pos.down(height * 0.5), pos.translate(width, height * 0.5), height)
+        } else {
+            (pos.right(width * 0.5), pos.translate(width * 0.5, height), width)
+        };
+        let dashes = match style {
+            RuleStyle::Solid => return self.rule(pos, width, height),
+            RuleStyle::Dashed => [3.0 * thickness as f32, 2.0 * thickness as f32],
+            RuleStyle::Dotted => [thickness as f32, thickness as f32],
+        };
+
+        let mut contour = Contour::new();
+        contour.push_endpoint(v_cursor(from));
+        contour.push_endpoint(v_cursor(to));
+        let mut outline = Outline::new();
+        outline.push_contour(contour);
+
+        let mut dash = OutlineDash::new(&outline, &dashes, 0.0);
+        dash.dash();
+        let style = StrokeStyle {
+            line_cap: LineCap::Butt,
+            line_join: LineJoin::Bevel,
+            line_width: thickness as f32
+        };
+        let mut stroke = OutlineStrokeToFill::new(&dash.into_outline(), style);
+        stroke.offset();
+        let outline = stroke.into_outline().transformed(&self.transform);
+        self.draw(outline, self.paint);
+    }
+    fn frame(&mut self, pos: Cursor, width: f64, height: f64, line_width: f64, radius: f64) {
+        // Stroke along the center of the line so that it lies inside the frame.
+        let inset = line_width * 0.5;
+        let rect = RectF::new(
+            v_cursor(pos.translate(inset, inset)),
+            v_xy(width - line_width, height - line_width)
+        );
+        let outline = Outline::from_rect_rounded(rect, v_xy(radius, radius));
+        let style = StrokeStyle {
+            line_cap: LineCap::Butt,
+            line_join: LineJoin::Miter(10.0),
+            line_width: line_width as f32
+        };
+        let mut stroke = OutlineStrokeToFill::new(&outline, style);
+        stroke.offset();
+        let outline = stroke.into_outline().transformed(&self.transform);
+        self.draw(outline, self.paint);
     }
+    /// A translucent color is composited over the surrounding color, so that
+    /// nested colors blend instead of replacing one another.
     fn begin_color(&mut self, RGBA(r, g, b, a): RGBA) {
-        self.color_stack.push(self.paint);
-        self.paint = self.scene.push_paint(&Paint::from_color(ColorU::new(r, g, b, a)));
+        self.color_stack.push((self.paint, self.color));
+        self.color = blend(ColorU::new(r, g, b, a), self.color);
+        self.paint = self.scene.push_paint(&Paint::from_color(self.color));
     }
     fn end_color(&mut self) {
-        self.paint = self.color_stack.pop().unwrap();
+        let (paint, color) = self.color_stack.pop().unwrap();
+        self.paint = paint;
+        self.color = color;
+    }
+    fn begin_transform(&mut self, origin: Cursor, transform: AffineTransform) {
+        self.transform_stack.push(self.transform);
+        self.transform = self.transform * transform_about(origin, transform);
+    }
+    fn end_transform(&mut self) {
+        self.transform = self.transform_stack.pop().unwrap();
+    }
+}
+
+/// Composite `over` on top of `under` ("source over").
+fn blend(over: ColorU, under: ColorU) -> ColorU {
+    if over.a == 255 {
+        return over;
+    }
+    let a = over.a as f32 / 255.;
+    let b = under.a as f32 / 255. * (1. - a);
+    let alpha = a + b;
+    if alpha == 0. {
+        return ColorU::transparent_black();
+    }
+    let mix = |x: u8, y: u8| ((x as f32 * a + y as f32 * b) / alpha).round() as u8;
+    ColorU::new(
+        mix(over.r, under.r),
+        mix(over.g, under.g),
+        mix(over.b, under.b),
+        (alpha * 255.).round() as u8
+    )
+}
+
+/// The file formats of `render_to_bytes`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+    Svg,
+    Pdf,
+    Ps,
+}
+
+impl From<Format> for FileFormat {
+    fn from(format: Format) -> FileFormat {
+        match format {
+            Format::Svg => FileFormat::SVG,
+            Format::Pdf => FileFormat::PDF,
+            Format::Ps => FileFormat::PS,
+        }
+    }
+}
+
+/// Lay out `nodes` and export them as a file of the given format, cropped
+/// to the size of the layout.  For raster images, use the `TinySkiaBackend`.
+pub fn render_to_bytes(nodes: &[ParseNode], settings: LayoutSettings, format: Format) -> Result<Vec<u8>, Error<'static>> {
+    let (layout, (x0, y0, x1, y1)) = engine::layout_and_size(nodes, settings)?;
+
+    let mut scene = Scene::new();
+    scene.set_view_box(RectF::from_points(v_xy(x0, y0), v_xy(x1, y1)));
+    Renderer::new().render(&layout, &mut SceneWrapper::new(&mut scene));
+
+    let mut buf = Vec::new();
+    scene.export(&mut buf, format.into()).expect("writing to a Vec does not fail");
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+    use crate::layout::Style;
+    use crate::dimensions::Unit;
+
+    fn assert_near(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn frame_is_one_stroked_path() {
+        let mut scene = Scene::new();
+        SceneWrapper::new(&mut scene).frame(Cursor { x: 1.0, y: 2.0 }, 10.0, 5.0, 0.5, 1.0);
+
+        // A single path, rather than four rules, whose stroke lies
+        // inside the frame.
+        assert_eq!(scene.draw_path_count(), 1);
+        let bounds = scene.bounds();
+        assert_near(bounds.min_x(), 1.0);
+        assert_near(bounds.min_y(), 2.0);
+        assert_near(bounds.max_x(), 11.0);
+        assert_near(bounds.max_y(), 7.0);
+    }
+
+    /// The number of contours drawn by `rule_styled` with `style`.
+    fn contours(style: RuleStyle) -> usize {
+        let mut scene = Scene::new();
+        scene.set_view_box(RectF::new(Vector2F::zero(), v_xy(40.0, 10.0)));
+        SceneWrapper::new(&mut scene).rule_styled(Cursor { x: 0.0, y: 4.0 }, 40.0, 1.0, style);
+        assert_eq!(scene.draw_path_count(), 1);
+
+        let mut svg = Vec::new();
+        scene.export(&mut svg, FileFormat::SVG).unwrap();
+        String::from_utf8(svg).unwrap().matches('M').count()
+    }
+
+    #[test]
+    fn dashed_rule_is_segmented() {
+        assert_eq!(contours(RuleStyle::Solid), 1);
+        assert!(contours(RuleStyle::Dashed) > 1);
+        assert!(contours(RuleStyle::Dotted) > contours(RuleStyle::Dashed));
+    }
+
+    #[test]
+    fn debug_adds_paths() {
+        let layout = engine::layout(&[ord('x'), ord('y')], settings(Style::Text)).unwrap();
+        let paths = |debug| {
+            let mut scene = Scene::new();
+            Renderer { debug, ..Renderer::new() }.render(&layout, &mut SceneWrapper::new(&mut scene));
+            scene.draw_path_count()
+        };
+
+        assert_eq!(paths(false), 2);
+        // A box for each glyph and around both, plus the baseline and axis.
+        assert!(paths(true) >= 2 + 3 + 2, "{}", paths(true));
+    }
+
+    #[test]
+    fn clip_skips_outside_paths() {
+        let nodes = [ord('x'), ParseNode::Kerning(Unit::Em(5.0)), ord('y')];
+        let layout = engine::layout(&nodes, settings(Style::Text)).unwrap();
+        let render = |clip| {
+            let mut scene = Scene::new();
+            let mut out = match clip {
+                Some(clip) => SceneWrapper::with_transform_and_clip(&mut scene, Transform2F::default(), clip),
+                None => SceneWrapper::new(&mut scene),
+            };
+            Renderer::new().render(&layout, &mut out);
+            scene.draw_path_count()
+        };
+
+        assert_eq!(render(None), 2);
+        // Only the `x` is within the clip.
+        assert_eq!(render(Some(RectF::new(v_xy(-5.0, -20.0), v_xy(20.0, 30.0)))), 1);
+    }
+
+    #[test]
+    fn nested_colors_blend() {
+        let mut scene = Scene::new();
+        let mut out = SceneWrapper::new(&mut scene);
+        out.begin_color(RGBA(0xff, 0, 0, 0xff));
+        out.begin_color(RGBA(0, 0, 0xff, 0x80));
+        assert_eq!(out.color, ColorU::new(0x7f, 0, 0x80, 0xff));
+
+        // An opaque color still replaces the one around it.
+        out.begin_color(RGBA(0, 0xff, 0, 0xff));
+        assert_eq!(out.color, ColorU::new(0, 0xff, 0, 0xff));
+
+        out.end_color();
+        out.end_color();
+        assert_eq!(out.color, ColorU::new(0xff, 0, 0, 0xff));
+    }
+
+    #[test]
+    fn exports_are_non_empty() {
+        let export = |format| render_to_bytes(&[ord('x')], settings(Style::Text), format).unwrap();
+
+        let pdf = export(Format::Pdf);
+        assert!(pdf.starts_with(b"%PDF"));
+        let svg = String::from_utf8(export(Format::Svg)).unwrap();
+        assert!(svg.contains("<svg"));
+        assert!(svg.contains("<path"));
+    }
+
+    #[test]
+    fn debug_stroke_width() {
+        for &line_width in &[0.1, 2.0] {
+            let mut scene = Scene::new();
+            let mut out = SceneWrapper::new(&mut scene);
+            out.debug_stroke.line_width = line_width;
+            out.bbox(Cursor { x: 0.0, y: 0.0 }, 10.0, 5.0, Role::Glyph);
+
+            // Half of the stroke lies outside the box.
+            let bounds = scene.bounds();
+            assert_near(bounds.min_x(), -line_width * 0.5);
+            assert_near(bounds.max_x(), 10.0 + line_width * 0.5);
+            assert_near(bounds.max_y(), 5.0 + line_width * 0.5);
+        }
     }
 }
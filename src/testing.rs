@@ -0,0 +1,41 @@
+//! Fixtures shared by the unit tests.  Layout is tested against the bundled
+//! XITS font, which has a complete MATH table.
+
+use font::OpenTypeFont;
+use crate::font::{AtomType, FontContext, MathFont};
+use crate::ast::{ParseNode, symbols::Symbol};
+use crate::layout::{LayoutSettings, Style};
+
+/// The mathematical italic small f, which has a large italics correction.
+pub const ITALIC_F: char = '\u{1D453}';
+
+pub fn font() -> &'static MathFont {
+    Box::leak(Box::new(OpenTypeFont::parse(include_bytes!("../data/xits/rex-xits.otf"))))
+}
+
+pub fn ctx() -> FontContext<'static> {
+    FontContext::new(font())
+}
+
+/// Settings for a 10px font in `style`.
+pub fn settings(style: Style) -> LayoutSettings<'static, 'static> {
+    LayoutSettings::new(Box::leak(Box::new(ctx())), 10.0, style)
+}
+
+pub fn sym(codepoint: char, atom_type: AtomType) -> ParseNode {
+    ParseNode::Symbol(Symbol { codepoint, atom_type })
+}
+
+/// An ordinary symbol, such as a letter or digit.
+pub fn ord(codepoint: char) -> ParseNode {
+    sym(codepoint, AtomType::Alpha)
+}
+
+/// The symbol for a TeX command, such as `sum` for `\sum`.
+pub fn named(name: &str) -> ParseNode {
+    ParseNode::Symbol(Symbol::from_name(name).expect("unknown symbol"))
+}
+
+pub fn assert_close(a: f64, b: f64) {
+    assert!((a - b).abs() < 1e-6, "{} != {}", a, b);
+}